@@ -0,0 +1,75 @@
+use std::fmt;
+
+use crate::value::Value;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AuditAction {
+    Insert,
+    Update,
+    Delete,
+}
+
+impl AuditAction {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AuditAction::Insert => "insert",
+            AuditAction::Update => "update",
+            AuditAction::Delete => "delete",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<AuditAction> {
+        [
+            AuditAction::Insert,
+            AuditAction::Update,
+            AuditAction::Delete,
+        ]
+        .iter()
+        .copied()
+        .find(|action| action.name() == name)
+    }
+}
+
+// One change written to `simple_table_audit`. Inserts and deletes get an entry for the row
+// (without a column) and one per non-NULL value. Values are stored as text, NULL as None.
+#[derive(Debug, PartialEq, Clone)]
+pub struct AuditEntry {
+    pub row_id: i32,
+    pub action: AuditAction,
+    pub column: Option<String>,
+    pub old_value: Option<String>,
+    pub new_value: Option<String>,
+    // MySQL user and host of the client that made the change
+    pub client: String,
+    pub changed_at: Value,
+}
+
+impl AuditEntry {
+    // Cell entries can be restored to their old value, row entries can't
+    pub fn is_restorable(&self) -> bool {
+        self.column.is_some()
+    }
+}
+
+impl fmt::Display for AuditEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = |value: &Option<String>| value.clone().unwrap_or_else(|| "NULL".to_owned());
+        write!(
+            f,
+            "{}  {}  {}",
+            self.changed_at,
+            self.client,
+            self.action.name()
+        )?;
+        match &self.column {
+            Some(column) => write!(
+                f,
+                " {}: {} → {}",
+                column,
+                text(&self.old_value),
+                text(&self.new_value)
+            ),
+            None => write!(f, " row {}", self.row_id),
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use clap::{App, Arg, ArgMatches, SubCommand};
+
+use crate::csv::{parse_separator, CsvOptions};
+use crate::database::Database;
+use crate::error::Error;
+use crate::transfer::{export_file, import_file, parse_mapping, PREVIEW_ROWS};
+use crate::validation::parse_rules;
+use crate::view::View;
+
+// Subcommands run without the GUI and exit
+pub fn subcommands() -> Vec<App<'static, 'static>> {
+    let csv_args = || {
+        vec![
+            Arg::with_name("delimiter")
+                .long("delimiter")
+                .takes_value(true)
+                .help("Field delimiter, `tab` for tabs [default: `,`, tab for .tsv files]"),
+            Arg::with_name("quote")
+                .long("quote")
+                .takes_value(true)
+                .help("Quote character [default: `\"`]"),
+        ]
+    };
+    vec![
+        SubCommand::with_name("import")
+            .about("Append rows from a CSV, XLSX, ODS, JSON or NDJSON file")
+            .arg(
+                Arg::with_name("file")
+                    .required(true)
+                    .help("File, the format follows the extension"),
+            )
+            .args(&csv_args())
+            .arg(
+                Arg::with_name("header")
+                    .long("header")
+                    .help("The first line is a header [default: detected]"),
+            )
+            .arg(
+                Arg::with_name("no-header")
+                    .long("no-header")
+                    .conflicts_with("header")
+                    .help("The first line is data"),
+            )
+            .arg(
+                Arg::with_name("map")
+                    .long("map")
+                    .takes_value(true)
+                    .help("Fields to import into other columns, e.g. `amount=number,label=B`"),
+            )
+            .arg(
+                Arg::with_name("dry-run")
+                    .long("dry-run")
+                    .help("Only show the preview and the errors"),
+            ),
+        SubCommand::with_name("export")
+            .about("Write the table to a CSV, XLSX, ODS, JSON or NDJSON file")
+            .arg(
+                Arg::with_name("file")
+                    .required(true)
+                    .help("File, the format follows the extension"),
+            )
+            .args(&csv_args())
+            .arg(
+                Arg::with_name("filter")
+                    .long("filter")
+                    .takes_value(true)
+                    .help("Filters like in the GUI, e.g. `number > 5; B ~ text`"),
+            )
+            .arg(
+                Arg::with_name("sort")
+                    .long("sort")
+                    .takes_value(true)
+                    .help("Sort keys, e.g. `number,-price` (`-` sorts descending)"),
+            ),
+    ]
+}
+
+fn csv_options(args: &ArgMatches, path: &str) -> Result<CsvOptions, Error> {
+    let mut options = CsvOptions::for_path(path);
+    if let Some(delimiter) = args.value_of("delimiter") {
+        options.delimiter = parse_separator(delimiter)?;
+    }
+    if let Some(quote) = args.value_of("quote") {
+        options.quote = parse_separator(quote)?;
+    }
+    if args.is_present("header") {
+        options.header = Some(true);
+    } else if args.is_present("no-header") {
+        options.header = Some(false);
+    }
+    Ok(options)
+}
+
+// Returns false if the subcommand is not one of ours
+pub fn run(db: &Database, name: &str, args: &ArgMatches) -> Result<bool, Error> {
+    match name {
+        "import" => import(db, args)?,
+        "export" => export(db, args)?,
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+fn import(db: &Database, args: &ArgMatches) -> Result<(), Error> {
+    let path = args.value_of("file").unwrap_or_default();
+    let columns = db.get_columns()?;
+    let mapping = parse_mapping(args.value_of("map").unwrap_or_default(), &columns)?;
+    let rules = parse_rules(db.get_rules()?);
+    let plan = import_file(path, &csv_options(args, path)?, &mapping, &columns, &rules)?;
+    println!("{}\n", plan.preview(PREVIEW_ROWS));
+    println!("{}", plan.report(plan.errors.len()));
+    if !args.is_present("dry-run") && !plan.rows.is_empty() {
+        let count = plan.rows.len();
+        db.insert_rows(&columns, plan.rows)?;
+        println!("Imported {} rows", count);
+    }
+    Ok(())
+}
+
+fn export(db: &Database, args: &ArgMatches) -> Result<(), Error> {
+    let path = args.value_of("file").unwrap_or_default();
+    let columns = db.get_columns()?;
+    let view = View {
+        filters: View::parse_filters(args.value_of("filter").unwrap_or_default(), &columns)?,
+        sort: View::parse_sort(args.value_of("sort").unwrap_or_default(), &columns)?,
+        ..Default::default()
+    };
+    let rows = db.get_rows(&columns, &view)?;
+    export_file(path, &csv_options(args, path)?, &columns, &rows, &[])?;
+    println!("Exported {} rows to {}", rows.len(), path);
+    Ok(())
+}
@@ -1,9 +1,11 @@
 use std::net::UdpSocket;
 use std::time::Duration;
 
-use mysql::serde_json::{from_slice, to_vec};
+use mysql::serde_json::{from_slice, to_vec, Value as JsonValue};
 
+use crate::database::Database;
 use crate::error::Error;
+use crate::sheet::Sheet;
 
 pub type MyConnectorResult<T> = std::result::Result<T, Error>;
 
@@ -48,7 +50,7 @@ impl Connector {
         Ok(())
     }
 
-    pub fn receive_data(&self) -> MyConnectorResult<Vec<Vec<String>>> {
+    pub fn receive_data(&self) -> MyConnectorResult<Sheet> {
         // TODO: Handle incoming data larger than the buffer
         let mut recv_buff = [0; 4800];
 
@@ -58,15 +60,15 @@ impl Connector {
 
                 let recieved_bytes = &mut recv_buff[..n];
 
-                let deserialized: Vec<Vec<String>> = from_slice(&recieved_bytes).unwrap();
-                Ok(deserialized)
+                let deserialized: JsonValue = from_slice(recieved_bytes)?;
+                Sheet::from_json(&deserialized)
             }
             Err(error) => return Err(error.into()),
         }
     }
 
-    pub fn send_data(&self, data: &Vec<Vec<String>>) -> MyConnectorResult<usize> {
-        let call: Vec<u8> = to_vec(&data.clone()).unwrap();
+    pub fn send_data(&self, db: &Database, sheet: &Sheet) -> MyConnectorResult<usize> {
+        let call: Vec<u8> = to_vec(&sheet.receiver_json(db))?;
         match self.socket.send_to(&call, self.connect_addr.clone()) {
             Ok(n_bytes) => {
                 if n_bytes != call.len() {
@@ -0,0 +1,246 @@
+use crate::error::Error;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CsvOptions {
+    pub delimiter: char,
+    pub quote: char,
+    // None detects the header from the first record
+    pub header: Option<bool>,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        CsvOptions {
+            delimiter: ',',
+            quote: '"',
+            header: None,
+        }
+    }
+}
+
+impl CsvOptions {
+    // Tab separated for .tsv files, comma separated otherwise
+    pub fn for_path(path: &str) -> CsvOptions {
+        let delimiter = if path.to_lowercase().ends_with(".tsv") {
+            '\t'
+        } else {
+            ','
+        };
+        CsvOptions {
+            delimiter,
+            ..Default::default()
+        }
+    }
+}
+
+// Accepts a single character or `tab`/`\t`
+pub fn parse_separator(input: &str) -> Result<char, Error> {
+    match input {
+        "tab" | "\\t" => Ok('\t'),
+        _ => {
+            let mut chars = input.chars();
+            match (chars.next(), chars.next()) {
+                (Some(separator), None) => Ok(separator),
+                _ => Err(Error::new(&format!(
+                    "Expected a single character, got \"{}\"",
+                    input
+                ))),
+            }
+        }
+    }
+}
+
+// A parsed line, `line` is where it starts in the file (quoted fields can span lines)
+#[derive(Debug, PartialEq, Clone)]
+pub struct Record {
+    pub line: usize,
+    pub fields: Vec<String>,
+}
+
+// RFC 4180: quoted fields may contain delimiters, line breaks and doubled quotes.
+// Empty lines are skipped.
+pub fn read(text: &str, options: &CsvOptions) -> Result<Vec<Record>, Error> {
+    parse(text, options, false)
+}
+
+fn parse(text: &str, options: &CsvOptions, keep_empty: bool) -> Result<Vec<Record>, Error> {
+    let text = text.trim_start_matches('\u{feff}');
+    let mut records = Vec::new();
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut line = 1;
+    let mut record_line = 1;
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if quoted {
+            if c == options.quote {
+                if chars.peek() == Some(&options.quote) {
+                    field.push(c);
+                    chars.next();
+                } else {
+                    quoted = false;
+                }
+            } else {
+                if c == '\n' {
+                    line += 1;
+                }
+                field.push(c);
+            }
+        } else if c == options.quote && field.is_empty() {
+            quoted = true;
+        } else if c == options.delimiter {
+            fields.push(std::mem::take(&mut field));
+        } else if c == '\n' || c == '\r' {
+            if c == '\r' && chars.peek() == Some(&'\n') {
+                chars.next();
+            }
+            fields.push(std::mem::take(&mut field));
+            if keep_empty || !(fields.len() == 1 && fields[0].is_empty()) {
+                records.push(Record {
+                    line: record_line,
+                    fields: std::mem::take(&mut fields),
+                });
+            }
+            fields.clear();
+            line += 1;
+            record_line = line;
+        } else {
+            field.push(c);
+        }
+    }
+
+    if quoted {
+        return Err(Error::new(&format!(
+            "Line {}: quoted field is not closed",
+            record_line
+        )));
+    }
+    if !field.is_empty() || !fields.is_empty() {
+        fields.push(field);
+        records.push(Record {
+            line: record_line,
+            fields,
+        });
+    }
+    Ok(records)
+}
+
+fn write_field(field: &str, options: &CsvOptions) -> String {
+    let special = |c: char| c == options.delimiter || c == options.quote || c == '\n' || c == '\r';
+    if field.chars().any(special) {
+        let quote = options.quote.to_string();
+        let doubled = format!("{}{}", quote, quote);
+        format!("{}{}{}", quote, field.replace(&quote, &doubled), quote)
+    } else {
+        field.to_owned()
+    }
+}
+
+// Fields are quoted only when needed, lines end with CRLF like RFC 4180 says
+pub fn write(records: &[Vec<String>], options: &CsvOptions) -> String {
+    let delimiter = options.delimiter.to_string();
+    records
+        .iter()
+        .map(|fields| {
+            let fields: Vec<String> = fields
+                .iter()
+                .map(|field| write_field(field, options))
+                .collect();
+            format!("{}\r\n", fields.join(&delimiter))
+        })
+        .collect()
+}
+
+// Cells copied from or pasted into other spreadsheets: tab separated, empty lines are
+// rows of empty cells
+pub fn write_clipboard(rows: &[Vec<String>]) -> String {
+    write(rows, &CsvOptions::for_path(".tsv"))
+}
+
+pub fn read_clipboard(text: &str) -> Result<Vec<Vec<String>>, Error> {
+    let records = parse(text, &CsvOptions::for_path(".tsv"), true)?;
+    Ok(records.into_iter().map(|record| record.fields).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::csv::{
+        parse_separator, read, read_clipboard, write, write_clipboard, CsvOptions, Record,
+    };
+
+    fn fields(records: &[Record]) -> Vec<Vec<&str>> {
+        records
+            .iter()
+            .map(|record| record.fields.iter().map(String::as_str).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_csv_read() {
+        let options = CsvOptions::default();
+        let records = read(
+            "\u{feff}number,text\r\n1,\"a, \"\"quoted\"\"\ntext\"\n\n2,\n3",
+            &options,
+        )
+        .unwrap();
+        assert_eq!(
+            fields(&records),
+            vec![
+                vec!["number", "text"],
+                vec!["1", "a, \"quoted\"\ntext"],
+                vec!["2", ""],
+                vec!["3"],
+            ]
+        );
+        let lines: Vec<usize> = records.iter().map(|record| record.line).collect();
+        assert_eq!(lines, vec![1, 2, 5, 6]);
+
+        let options = CsvOptions {
+            delimiter: ';',
+            quote: '\'',
+            header: None,
+        };
+        assert_eq!(
+            fields(&read("a;'b;c'\n", &options).unwrap()),
+            vec![vec!["a", "b;c"]]
+        );
+        assert!(read("1,\"open\n2", &CsvOptions::default()).is_err());
+    }
+
+    #[test]
+    fn test_csv_write() {
+        let records = vec![
+            vec!["number".to_owned(), "text".to_owned()],
+            vec!["1".to_owned(), "a, \"quoted\"\ntext".to_owned()],
+        ];
+        let options = CsvOptions::default();
+        let text = write(&records, &options);
+        assert_eq!(text, "number,text\r\n1,\"a, \"\"quoted\"\"\ntext\"\r\n");
+        assert_eq!(
+            fields(&read(&text, &options).unwrap()),
+            vec![vec!["number", "text"], vec!["1", "a, \"quoted\"\ntext"],]
+        );
+
+        assert_eq!(parse_separator("tab").unwrap(), '\t');
+        assert_eq!(parse_separator(";").unwrap(), ';');
+        assert!(parse_separator(";;").is_err());
+    }
+
+    #[test]
+    fn test_clipboard() {
+        let rows = vec![
+            vec!["1".to_owned(), "two\tlines\n".to_owned()],
+            vec![String::new(), String::new()],
+            vec!["3".to_owned(), String::new()],
+        ];
+        let text = write_clipboard(&rows);
+        assert_eq!(text, "1\t\"two\tlines\n\"\r\n\t\r\n3\t\r\n");
+        assert_eq!(read_clipboard(&text).unwrap(), rows);
+
+        // A single column with an empty cell, as other spreadsheets copy it
+        let column = read_clipboard("a\n\nb\n").unwrap();
+        assert_eq!(column, vec![vec!["a"], vec![""], vec!["b"]]);
+    }
+}
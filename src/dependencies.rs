@@ -0,0 +1,236 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::hash::Hash;
+
+use crate::formula::{CellRef, Reference};
+
+// Which formulas refer to which cells, as the formulas name them. A range is one entry
+// however many cells it covers.
+#[derive(Default, Debug, PartialEq, Clone)]
+pub struct DependencyGraph {
+    // Cells and ranges every formula refers to
+    precedents: HashMap<CellRef, HashSet<Reference>>,
+    // Formulas referring to every single cell
+    dependents: HashMap<CellRef, HashSet<CellRef>>,
+    // Formulas referring to every range
+    ranges: HashMap<Reference, HashSet<CellRef>>,
+}
+
+impl DependencyGraph {
+    pub fn clear(&mut self) {
+        self.precedents.clear();
+        self.dependents.clear();
+        self.ranges.clear();
+    }
+
+    // Replaces what the formula at `cell` refers to
+    pub fn set(&mut self, cell: CellRef, references: Vec<Reference>) {
+        self.remove(cell);
+        for reference in references.iter() {
+            if reference.is_cell() {
+                self.dependents
+                    .entry(reference.first)
+                    .or_default()
+                    .insert(cell);
+            } else {
+                self.ranges.entry(*reference).or_default().insert(cell);
+            }
+        }
+        self.precedents
+            .insert(cell, references.into_iter().collect());
+    }
+
+    // The cell holds no formula anymore
+    pub fn remove(&mut self, cell: CellRef) {
+        for reference in self.precedents.remove(&cell).unwrap_or_default() {
+            if reference.is_cell() {
+                remove_dependent(&mut self.dependents, reference.first, cell);
+            } else {
+                remove_dependent(&mut self.ranges, reference, cell);
+            }
+        }
+    }
+
+    // Formulas referring to the cell, once for every reference covering it
+    fn dependents_of(&self, cell: CellRef) -> impl Iterator<Item = &CellRef> {
+        let ranges = self
+            .ranges
+            .iter()
+            .filter(move |(range, _)| range.contains(cell))
+            .flat_map(|(_, dependents)| dependents);
+        self.dependents
+            .get(&cell)
+            .into_iter()
+            .flatten()
+            .chain(ranges)
+    }
+
+    pub fn is_formula(&self, cell: CellRef) -> bool {
+        self.precedents.contains_key(&cell)
+    }
+
+    // Formulas to compute again after the cells changed: the changed formulas and every
+    // formula depending on a changed cell, directly or through other formulas
+    pub fn affected(&self, changed: &[CellRef]) -> HashSet<CellRef> {
+        let mut affected: HashSet<CellRef> = changed
+            .iter()
+            .copied()
+            .filter(|cell| self.is_formula(*cell))
+            .collect();
+        let mut queue: VecDeque<CellRef> = changed.iter().copied().collect();
+        while let Some(cell) = queue.pop_front() {
+            for dependent in self.dependents_of(cell) {
+                if affected.insert(*dependent) {
+                    queue.push_back(*dependent);
+                }
+            }
+        }
+        affected
+    }
+
+    // Orders the formulas so every one comes after the ones it refers to (Kahn's
+    // algorithm). Formulas that can't be ordered are in a cycle or depend on one, they
+    // are returned second.
+    pub fn order(&self, cells: &HashSet<CellRef>) -> (Vec<CellRef>, Vec<CellRef>) {
+        // Number of formulas in `cells` each formula still waits for
+        let mut waiting: HashMap<CellRef, usize> = cells
+            .iter()
+            .map(|cell| {
+                let precedents = self.precedents.get(cell).into_iter().flatten();
+                let count = precedents
+                    .map(|reference| match reference.is_cell() {
+                        true => cells.contains(&reference.first) as usize,
+                        false => cells
+                            .iter()
+                            .filter(|other| reference.contains(**other))
+                            .count(),
+                    })
+                    .sum();
+                (*cell, count)
+            })
+            .collect();
+        let mut ready: Vec<CellRef> = waiting
+            .iter()
+            .filter(|(_, count)| **count == 0)
+            .map(|(cell, _)| *cell)
+            .collect();
+        // Sorted so the order doesn't depend on hashing
+        ready.sort_unstable_by(|a, b| b.cmp(a));
+
+        let mut order = Vec::with_capacity(cells.len());
+        while let Some(cell) = ready.pop() {
+            order.push(cell);
+            waiting.remove(&cell);
+            for dependent in self.dependents_of(cell) {
+                if let Some(count) = waiting.get_mut(dependent) {
+                    *count -= 1;
+                    if *count == 0 {
+                        ready.push(*dependent);
+                    }
+                }
+            }
+        }
+        let mut cyclic: Vec<CellRef> = waiting.into_keys().collect();
+        cyclic.sort_unstable();
+        (order, cyclic)
+    }
+}
+
+// Takes the formula out of the ones referring to `key`, dropping the entry once it's empty
+fn remove_dependent<K: Eq + Hash>(map: &mut HashMap<K, HashSet<CellRef>>, key: K, cell: CellRef) {
+    if let Some(dependents) = map.get_mut(&key) {
+        dependents.remove(&cell);
+        if dependents.is_empty() {
+            map.remove(&key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dependencies::DependencyGraph;
+    use crate::formula::{CellRef, Reference};
+    use std::collections::HashSet;
+
+    fn cell(label: &str) -> CellRef {
+        CellRef::parse(label).unwrap()
+    }
+
+    fn cells(labels: &[&str]) -> Vec<CellRef> {
+        labels.iter().map(|label| cell(label)).collect()
+    }
+
+    // Cells like `A1` and ranges like `A1:B3`
+    fn references(labels: &[&str]) -> Vec<Reference> {
+        labels
+            .iter()
+            .map(|label| match label.split_once(':') {
+                Some((start, end)) => Reference::range(cell(start), cell(end)),
+                None => Reference::cell(cell(label)),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_dependency_order() {
+        let mut graph = DependencyGraph::default();
+        // B1 = A1, C1 = A1 + B1, D1 = C1, E1 = 5
+        graph.set(cell("B1"), references(&["A1"]));
+        graph.set(cell("C1"), references(&["A1", "B1", "A1"]));
+        graph.set(cell("D1"), references(&["C1"]));
+        graph.set(cell("E1"), Vec::new());
+
+        let affected = graph.affected(&cells(&["A1"]));
+        assert_eq!(affected, cells(&["B1", "C1", "D1"]).into_iter().collect());
+        let (order, cyclic) = graph.order(&affected);
+        assert_eq!(order, cells(&["B1", "C1", "D1"]));
+        assert!(cyclic.is_empty());
+        assert_eq!(graph.affected(&cells(&["D1", "E1", "F1"])).len(), 2);
+
+        // B1 = D1 closes a cycle, D1 depends on it and so does nothing else
+        graph.set(cell("B1"), references(&["D1"]));
+        let affected = graph.affected(&cells(&["B1"]));
+        assert_eq!(
+            graph.order(&affected),
+            (Vec::new(), cells(&["B1", "C1", "D1"]))
+        );
+
+        graph.remove(cell("B1"));
+        let (order, cyclic) = graph.order(&graph.affected(&cells(&["B1"])));
+        assert_eq!(order, cells(&["C1", "D1"]));
+        assert!(cyclic.is_empty());
+        assert!(!graph.is_formula(cell("B1")));
+
+        // A formula referring to itself
+        graph.set(cell("A2"), references(&["A2"]));
+        let affected: HashSet<CellRef> = graph.affected(&cells(&["A2"]));
+        assert_eq!(graph.order(&affected), (Vec::new(), cells(&["A2"])));
+    }
+
+    #[test]
+    fn test_dependency_ranges() {
+        let mut graph = DependencyGraph::default();
+        // F1 = SUM(A1:A3), G1 = SUM(F1:F1000000), every range is one entry
+        graph.set(cell("F1"), references(&["A1:A3"]));
+        graph.set(cell("G1"), references(&["F1:F1000000"]));
+        let affected = graph.affected(&cells(&["A2"]));
+        assert_eq!(affected, cells(&["F1", "G1"]).into_iter().collect());
+        assert_eq!(graph.order(&affected), (cells(&["F1", "G1"]), Vec::new()));
+        assert!(graph.affected(&cells(&["A4", "B2"])).is_empty());
+
+        // F5 = G1 + A3 is inside the range G1 refers to
+        graph.set(cell("F5"), references(&["G1", "A3"]));
+        let affected = graph.affected(&cells(&["A3"]));
+        assert_eq!(
+            graph.order(&affected),
+            (cells(&["F1"]), cells(&["G1", "F5"]))
+        );
+
+        graph.remove(cell("F5"));
+        graph.remove(cell("F1"));
+        assert_eq!(graph.affected(&cells(&["A2"])), HashSet::new());
+        assert_eq!(
+            graph.affected(&cells(&["F3"])),
+            cells(&["G1"]).into_iter().collect()
+        );
+    }
+}
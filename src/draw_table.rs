@@ -1,10 +1,42 @@
+use std::collections::BTreeSet;
+
 use fltk::draw::{
-    draw_box, draw_rect, draw_rectf, draw_text2, pop_clip, push_clip, set_draw_color,
+    draw_box, draw_rect, draw_rectf, draw_text2, pop_clip, push_clip, set_draw_color, set_font,
 };
-use fltk::{Align, Color, FrameType};
+use fltk::{Align, Color, Font, FrameType};
+
+use crate::formatting::Style;
+use crate::navigation::Selection;
+use crate::value::Value;
+
+// Columns up to XFD, like spreadsheets have
+pub const MAX_LABELED_COLUMNS: usize = 16_384;
+
+// Spreadsheet column label: A, ..., Z, AA, ..., AZ, BA, ..., XFD
+pub fn column_label(col: usize) -> String {
+    let mut letters = Vec::new();
+    let mut col = col + 1;
+    while col > 0 {
+        letters.push((b'A' + ((col - 1) % 26) as u8) as char);
+        col = (col - 1) / 26;
+    }
+    letters.iter().rev().collect()
+}
+
+// Column of an upper case label, None past XFD
+pub fn parse_column_label(label: &str) -> Option<usize> {
+    if label.is_empty() || label.len() > 3 || !label.chars().all(|c| c.is_ascii_uppercase()) {
+        return None;
+    }
+    let number = label.bytes().fold(0, |number, letter| {
+        number * 26 + (letter - b'A' + 1) as usize
+    });
+    Some(number - 1).filter(|col| *col < MAX_LABELED_COLUMNS)
+}
 
-pub fn get_alpha_upper_char(char_index: i32) -> char {
-    (char_index + 65) as u8 as char
+// Spreadsheet style name of a cell, e.g. B3
+pub fn cell_name(row: usize, col: usize) -> String {
+    format!("{}{}", column_label(col), row + 1)
 }
 
 // Needed to store cell information during the draw_cell call
@@ -16,6 +48,16 @@ pub struct CellData {
     pub y: i32,
     pub w: i32,
     pub h: i32,
+    // Selected range, `row` and `col` are its cursor
+    pub selection: Selection,
+    // Center of the fill handle if it's on screen
+    pub handle: Option<(i32, i32)>,
+    // Range covered while the fill handle is dragged
+    pub fill: Option<Selection>,
+    // Matches of the find bar
+    pub found: BTreeSet<(usize, usize)>,
+    // Bounds of the color scales, computed when drawing starts
+    pub scale_bounds: Vec<Option<(f64, f64)>>,
 }
 
 impl CellData {
@@ -27,6 +69,13 @@ impl CellData {
         self.w = w;
         self.h = h;
     }
+
+    // Moves the cursor before the cell is drawn, key presses until then need it
+    pub fn set_selection(&mut self, selection: Selection) {
+        self.selection = selection;
+        self.row = selection.cursor.0 as i32;
+        self.col = selection.cursor.1 as i32;
+    }
 }
 
 pub fn draw_header(txt: &str, x: i32, y: i32, w: i32, h: i32) {
@@ -37,18 +86,94 @@ pub fn draw_header(txt: &str, x: i32, y: i32, w: i32, h: i32) {
     pop_clip();
 }
 
-// The selected flag sets the color of the cell to a grayish color, otherwise white
-pub fn draw_data(txt: &str, x: i32, y: i32, w: i32, h: i32, selected: bool) {
+// Numbers are right aligned, flags centered and everything else left aligned
+fn value_align(value: &Value) -> Align {
+    match value {
+        Value::Integer(_) | Value::Float(_) | Value::Decimal(_) => Align::Right,
+        Value::Boolean(_) => Align::Center,
+        _ => Align::Left,
+    }
+}
+
+// Grayish for selected cells, yellow for matches of the find bar, otherwise the one of
+// the conditional formats or white
+pub fn cell_background(selected: bool, found: bool, formatted: Option<u32>) -> u32 {
+    match (selected, found) {
+        (true, true) => 0xE0D080,
+        (true, false) => 0xD3D3D3,
+        (false, true) => 0xFFF0A0,
+        (false, false) => formatted.unwrap_or(0xFFFFFF),
+    }
+}
+
+fn style_font(style: &Style) -> Font {
+    match (style.bold, style.italic) {
+        (true, true) => Font::HelveticaBoldItalic,
+        (true, false) => Font::HelveticaBold,
+        (false, true) => Font::HelveticaItalic,
+        (false, false) => Font::Helvetica,
+    }
+}
+
+// Cells breaking a validation rule get a red frame
+pub fn draw_data(value: &Value, x: i32, y: i32, w: i32, h: i32, style: &Style, invalid: bool) {
     push_clip(x, y, w, h);
 
-    set_draw_color(if selected {
-        Color::from_u32(0xD3D3D3)
+    set_draw_color(Color::from_u32(style.background.unwrap_or(0xFFFFFF)));
+    draw_rectf(x, y, w, h);
+    set_draw_color(if value.is_null() {
+        Color::from_u32(0xA0A0A0)
     } else {
-        Color::White
+        Color::from_u32(style.foreground.unwrap_or(0x000000))
     });
-    draw_rectf(x, y, w, h);
+    set_font(style_font(style), 14);
+    let txt = if value.is_null() {
+        "NULL".to_owned()
+    } else {
+        value.to_string()
+    };
+    draw_text2(&txt, x + 4, y, w - 8, h, value_align(value));
+    set_font(Font::Helvetica, 14);
     set_draw_color(Color::Gray0);
-    draw_text2(txt, x, y, w, h, Align::Center);
     draw_rect(x, y, w, h);
+    if invalid {
+        set_draw_color(Color::Red);
+        draw_rect(x + 1, y + 1, w - 2, h - 2);
+        draw_rect(x + 2, y + 2, w - 4, h - 4);
+    }
+    pop_clip();
+}
+
+pub const FILL_HANDLE_SIZE: i32 = 6;
+
+// Square in the bottom right corner of the selection, dragged to fill cells. Returns its
+// center.
+pub fn draw_fill_handle(x: i32, y: i32, w: i32, h: i32) -> (i32, i32) {
+    let (left, top) = (x + w - FILL_HANDLE_SIZE, y + h - FILL_HANDLE_SIZE);
+    push_clip(x, y, w, h);
+    set_draw_color(Color::Black);
+    draw_rectf(left, top, FILL_HANDLE_SIZE, FILL_HANDLE_SIZE);
     pop_clip();
+    (left + FILL_HANDLE_SIZE / 2, top + FILL_HANDLE_SIZE / 2)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::draw_table::{cell_name, column_label, parse_column_label};
+
+    #[test]
+    fn test_column_labels() {
+        let labels: Vec<String> = [0, 25, 26, 51, 52, 701, 702, 16_383]
+            .iter()
+            .map(|col| column_label(*col))
+            .collect();
+        assert_eq!(labels, vec!["A", "Z", "AA", "AZ", "BA", "ZZ", "AAA", "XFD"]);
+        for col in 0..16_384 {
+            assert_eq!(parse_column_label(&column_label(col)), Some(col));
+        }
+        for invalid in &["", "XFE", "ZZZZ", "a", "A1", "["] {
+            assert_eq!(parse_column_label(invalid), None, "{}", invalid);
+        }
+        assert_eq!(cell_name(9, 27), "AB10");
+    }
 }
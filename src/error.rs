@@ -2,6 +2,7 @@ use std::io::Error as IoError;
 use std::num::ParseIntError;
 
 use mysql;
+use mysql::serde_json;
 
 #[derive(Debug)]
 pub struct Error {
@@ -33,3 +34,21 @@ impl From<IoError> for Error {
         Error::new(err.to_string().as_str())
     }
 }
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Error::new(err.to_string().as_str())
+    }
+}
+
+impl From<zip::result::ZipError> for Error {
+    fn from(err: zip::result::ZipError) -> Self {
+        Error::new(err.to_string().as_str())
+    }
+}
+
+impl From<quick_xml::Error> for Error {
+    fn from(err: quick_xml::Error) -> Self {
+        Error::new(err.to_string().as_str())
+    }
+}
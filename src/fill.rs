@@ -0,0 +1,183 @@
+use std::convert::TryFrom;
+
+use crate::value::{days_in_month, Date};
+
+// Continues the seed values by `count` more if they form a series: numbers by their step,
+// dates by days (by months when the day of the month stays), text ending in a number by
+// counting on. None if they don't, filling repeats them then.
+pub fn series(seeds: &[String], count: usize) -> Option<Vec<String>> {
+    if seeds.is_empty() {
+        return None;
+    }
+    numbers(seeds, count)
+        .or_else(|| dates(seeds, count))
+        .or_else(|| numbered_text(seeds, count))
+}
+
+// Step between the first and the last of `values` per position
+fn step(values: &[f64]) -> f64 {
+    match values {
+        [first, .., last] => (last - first) / (values.len() - 1) as f64,
+        _ => 0.0,
+    }
+}
+
+fn numbers(seeds: &[String], count: usize) -> Option<Vec<String>> {
+    // A single number is repeated, like spreadsheets do
+    if seeds.len() < 2 {
+        return None;
+    }
+    let values = seeds
+        .iter()
+        .map(|seed| {
+            seed.trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|value| value.is_finite())
+        })
+        .collect::<Option<Vec<f64>>>()?;
+    // The series keeps the precision of the seeds
+    let decimals = seeds
+        .iter()
+        .map(|seed| seed.trim().split('.').nth(1).map_or(0, str::len))
+        .max()
+        .unwrap_or(0);
+    let scale = 10f64.powi(decimals as i32);
+    let step = (step(&values) * scale).round() / scale;
+    let last = values[values.len() - 1];
+    Some(
+        (1..=count)
+            .map(|index| format!("{:.*}", decimals, last + step * index as f64))
+            .collect(),
+    )
+}
+
+fn dates(seeds: &[String], count: usize) -> Option<Vec<String>> {
+    let dates = seeds
+        .iter()
+        .map(|seed| Date::parse(seed.trim()))
+        .collect::<Option<Vec<Date>>>()?;
+    let last = dates[dates.len() - 1];
+
+    let months: Vec<f64> = dates
+        .iter()
+        .map(|date| f64::from(date.year) * 12.0 + f64::from(date.month - 1))
+        .collect();
+    let month_step = step(&months);
+    let same_day = dates.iter().all(|date| date.day == last.day);
+    if dates.len() > 1 && same_day && month_step != 0.0 && month_step.fract() == 0.0 {
+        let last_month = months[months.len() - 1] as i64;
+        return (1..=count as i64)
+            .map(|index| {
+                let month = last_month + month_step as i64 * index;
+                let year = u16::try_from(month.div_euclid(12)).ok()?;
+                let month = month.rem_euclid(12) as u8 + 1;
+                // The 31st continues as the last day of shorter months
+                let day = last.day.min(days_in_month(year, month));
+                Some(Date { year, month, day }.to_string())
+            })
+            .collect();
+    }
+
+    let days: Vec<f64> = dates
+        .iter()
+        .map(|date| date.days_since_epoch() as f64)
+        .collect();
+    let day_step = if dates.len() == 1 {
+        1
+    } else {
+        step(&days).round() as i64
+    };
+    let last_day = last.days_since_epoch();
+    (1..=count as i64)
+        .map(|index| Some(Date::from_days(last_day + day_step * index)?.to_string()))
+        .collect()
+}
+
+// Text ending in a number, e.g. `Item 1`, `Q01`
+fn numbered_text(seeds: &[String], count: usize) -> Option<Vec<String>> {
+    let split = |seed: &str| -> Option<(String, String)> {
+        let prefix = seed.trim_end_matches(|c: char| c.is_ascii_digit());
+        let digits = &seed[prefix.len()..];
+        if prefix.is_empty() || digits.is_empty() {
+            return None;
+        }
+        Some((prefix.to_owned(), digits.to_owned()))
+    };
+    let parts = seeds
+        .iter()
+        .map(|seed| split(seed))
+        .collect::<Option<Vec<(String, String)>>>()?;
+    let (prefix, last_digits) = &parts[parts.len() - 1];
+    if parts.iter().any(|(other, _)| other != prefix) {
+        return None;
+    }
+    let numbers = parts
+        .iter()
+        .map(|(_, digits)| digits.parse::<i64>().ok().map(|number| number as f64))
+        .collect::<Option<Vec<f64>>>()?;
+    let step = if numbers.len() == 1 {
+        1
+    } else {
+        step(&numbers).round() as i64
+    };
+    // Zero padded numbers stay padded
+    let width = if last_digits.starts_with('0') {
+        last_digits.len()
+    } else {
+        0
+    };
+    let last = numbers[numbers.len() - 1] as i64;
+    Some(
+        (1..=count as i64)
+            .map(|index| {
+                let number = (last + step * index).max(0);
+                format!("{}{:0width$}", prefix, number, width = width)
+            })
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fill::series;
+
+    fn seeds(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn test_series() {
+        assert_eq!(series(&seeds(&["1", "2"]), 3).unwrap(), ["3", "4", "5"]);
+        assert_eq!(series(&seeds(&["10", "7"]), 2).unwrap(), ["4", "1"]);
+        assert_eq!(
+            series(&seeds(&["0.5", "0.75"]), 2).unwrap(),
+            ["1.00", "1.25"]
+        );
+        assert_eq!(series(&seeds(&["1", "2", "4"]), 1).unwrap(), ["6"]);
+        // Single numbers and plain text are repeated
+        assert_eq!(series(&seeds(&["5"]), 2), None);
+        assert_eq!(series(&seeds(&["a", "b"]), 2), None);
+        assert_eq!(series(&seeds(&["1", ""]), 2), None);
+
+        assert_eq!(
+            series(&seeds(&["2020-02-27"]), 3).unwrap(),
+            ["2020-02-28", "2020-02-29", "2020-03-01"]
+        );
+        assert_eq!(
+            series(&seeds(&["2020-01-01", "2020-01-08"]), 1).unwrap(),
+            ["2020-01-15"]
+        );
+        assert_eq!(
+            series(&seeds(&["2020-11-30", "2020-12-30"]), 2).unwrap(),
+            ["2021-01-30", "2021-02-28"]
+        );
+
+        assert_eq!(
+            series(&seeds(&["Item 1"]), 2).unwrap(),
+            ["Item 2", "Item 3"]
+        );
+        assert_eq!(series(&seeds(&["Q08", "Q10"]), 1).unwrap(), ["Q12"]);
+        assert_eq!(series(&seeds(&["Item 1", "Part 2"]), 1), None);
+    }
+}
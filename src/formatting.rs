@@ -0,0 +1,310 @@
+use std::fmt;
+
+use crate::error::Error;
+use crate::formula::{CellRef, Formula};
+use crate::view::Operator;
+
+// Cells a conditional format applies to: a whole column by name, or a range of sheet
+// positions like `B2:C10`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Target {
+    Column(String),
+    Range(CellRef, CellRef),
+}
+
+impl Target {
+    // Ranges are cell labels, anything else is a column name
+    pub fn parse(text: &str) -> Target {
+        let text = text.trim();
+        let mut corners = text
+            .splitn(2, ':')
+            .map(|label| CellRef::parse(label.trim()));
+        match (corners.next().flatten(), corners.next()) {
+            (Some(first), None) => Target::Range(first, first),
+            (Some(first), Some(Some(last))) => Target::Range(
+                CellRef {
+                    row: first.row.min(last.row),
+                    col: first.col.min(last.col),
+                },
+                CellRef {
+                    row: first.row.max(last.row),
+                    col: first.col.max(last.col),
+                },
+            ),
+            _ => Target::Column(text.to_owned()),
+        }
+    }
+
+    // Position of the cell from the first one of the target, None if it's outside.
+    // `column` is the name of the cell's column.
+    pub fn offset(&self, row: usize, col: usize, column: &str) -> Option<(usize, usize)> {
+        match self {
+            Target::Column(name) if name == column => Some((row, 0)),
+            Target::Column(_) => None,
+            Target::Range(first, last) => {
+                let inside =
+                    (first.row..=last.row).contains(&row) && (first.col..=last.col).contains(&col);
+                Some((row - first.row, col - first.col)).filter(|_| inside)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Target::Column(name) => write!(f, "{}", name),
+            Target::Range(first, last) if first == last => write!(f, "{}", first),
+            Target::Range(first, last) => write!(f, "{}:{}", first, last),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    // Compares the value the cell shows, `operand` is parsed with the column type
+    Compare(Operator, String),
+    // True if the formula, written for the first cell of the target, is. Other cells move
+    // its references along like filling does.
+    Formula(Formula),
+    // Background from the first color for the smallest number to the second for the largest
+    Scale(u32, u32),
+}
+
+// Colors are 0xRRGGBB
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct Style {
+    pub background: Option<u32>,
+    pub foreground: Option<u32>,
+    pub bold: bool,
+    pub italic: bool,
+}
+
+impl Style {
+    // What `other` sets wins
+    pub fn merge(&mut self, other: &Style) {
+        self.background = other.background.or(self.background);
+        self.foreground = other.foreground.or(self.foreground);
+        self.bold |= other.bold;
+        self.italic |= other.italic;
+    }
+}
+
+const COLORS: [(&str, u32); 9] = [
+    ("black", 0x000000),
+    ("white", 0xFFFFFF),
+    ("gray", 0x808080),
+    ("red", 0xC00000),
+    ("orange", 0xFFA500),
+    ("yellow", 0xFFEB84),
+    ("green", 0x00B050),
+    ("blue", 0x0070C0),
+    ("purple", 0x7030A0),
+];
+
+// `#RRGGBB` or one of the named colors
+pub fn parse_color(text: &str) -> Option<u32> {
+    let text = text.trim();
+    match text.strip_prefix('#') {
+        Some(hex) if hex.len() == 6 => u32::from_str_radix(hex, 16).ok(),
+        Some(_) => None,
+        None => COLORS
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(text))
+            .map(|(_, color)| *color),
+    }
+}
+
+// Color a `share` (0 to 1) of the way from `low` to `high`
+pub fn blend(low: u32, high: u32, share: f64) -> u32 {
+    let share = share.clamp(0.0, 1.0);
+    [16, 8, 0].iter().fold(0, |color, shift| {
+        let low = f64::from((low >> shift) & 0xFF);
+        let high = f64::from((high >> shift) & 0xFF);
+        color | (((low + (high - low) * share).round() as u32) << shift)
+    })
+}
+
+// A conditional format, written like `> 100 => background #FFC7CE, bold`. Conditions are
+// comparisons as in filters (`= done`, `~ text`), `formula =A1 > B1` or
+// `scale #F8696B #63BE7B`; styles are `background <color>`, `color <color>`, `bold` and
+// `italic` separated by `,`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FormatRule {
+    pub target: Target,
+    pub condition: Condition,
+    pub style: Style,
+}
+
+impl FormatRule {
+    pub fn parse(target: &str, input: &str) -> Result<FormatRule, Error> {
+        let invalid = |part: &str| Error::new(&format!("Invalid format \"{}\"", part.trim()));
+        let (condition, style) = match input.rfind("=>") {
+            Some(index) => (input[..index].trim(), input[index + 2..].trim()),
+            None => (input.trim(), ""),
+        };
+
+        let condition = if let Some(source) = condition.strip_prefix("formula") {
+            Condition::Formula(Formula::parse(source.trim())?)
+        } else if let Some(colors) = condition.strip_prefix("scale") {
+            let colors: Vec<u32> = colors
+                .split_whitespace()
+                .map(parse_color)
+                .collect::<Option<Vec<u32>>>()
+                .ok_or_else(|| invalid(condition))?;
+            match colors.as_slice() {
+                [low, high] => Condition::Scale(*low, *high),
+                _ => return Err(invalid(condition)),
+            }
+        } else {
+            let (operator, operand) =
+                Operator::split_prefix(condition).ok_or_else(|| invalid(condition))?;
+            let operand = operand.trim();
+            let operand = operand
+                .strip_prefix('"')
+                .and_then(|operand| operand.strip_suffix('"'))
+                .unwrap_or(operand);
+            Condition::Compare(operator, operand.to_owned())
+        };
+
+        let mut parsed = Style::default();
+        for part in style
+            .split(',')
+            .map(str::trim)
+            .filter(|part| !part.is_empty())
+        {
+            let mut words = part.splitn(2, char::is_whitespace);
+            let (name, argument) = (words.next().unwrap_or_default(), words.next());
+            let color = || argument.and_then(parse_color).ok_or_else(|| invalid(part));
+            match (name.to_lowercase().as_str(), argument) {
+                ("background", _) => parsed.background = Some(color()?),
+                ("color", _) => parsed.foreground = Some(color()?),
+                ("bold", None) => parsed.bold = true,
+                ("italic", None) => parsed.italic = true,
+                _ => return Err(invalid(part)),
+            }
+        }
+        let scale = matches!(condition, Condition::Scale(..));
+        if parsed == Style::default() && !scale {
+            return Err(Error::new("The format sets no style"));
+        }
+        Ok(FormatRule {
+            target: Target::parse(target),
+            condition,
+            style: parsed,
+        })
+    }
+}
+
+impl fmt::Display for FormatRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.condition {
+            Condition::Compare(operator, operand) => {
+                write!(f, "{} {}", operator.symbol(), operand)?
+            }
+            Condition::Formula(formula) => write!(f, "formula {}", formula.source)?,
+            Condition::Scale(low, high) => write!(f, "scale #{:06X} #{:06X}", low, high)?,
+        }
+        let mut parts = Vec::new();
+        if let Some(background) = self.style.background {
+            parts.push(format!("background #{:06X}", background));
+        }
+        if let Some(foreground) = self.style.foreground {
+            parts.push(format!("color #{:06X}", foreground));
+        }
+        if self.style.bold {
+            parts.push("bold".to_owned());
+        }
+        if self.style.italic {
+            parts.push("italic".to_owned());
+        }
+        if !parts.is_empty() {
+            write!(f, " => {}", parts.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+// Parses (target, format) pairs in order, formats that don't parse are dropped
+pub fn parse_formats(formats: Vec<(String, String)>) -> Vec<FormatRule> {
+    formats
+        .into_iter()
+        .filter_map(|(target, format)| FormatRule::parse(&target, &format).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::formatting::{blend, parse_color, Condition, FormatRule, Style, Target};
+    use crate::formula::CellRef;
+    use crate::view::Operator;
+
+    #[test]
+    fn test_target() {
+        let range = Target::parse("C5:B2");
+        assert_eq!(
+            range,
+            Target::Range(CellRef { row: 1, col: 1 }, CellRef { row: 4, col: 2 })
+        );
+        assert_eq!(range.to_string(), "B2:C5");
+        assert_eq!(range.offset(3, 2, "price"), Some((2, 1)));
+        assert_eq!(range.offset(5, 2, "price"), None);
+        assert_eq!(Target::parse("A1").to_string(), "A1");
+
+        let column = Target::parse("price");
+        assert_eq!(column.offset(7, 2, "price"), Some((7, 0)));
+        assert_eq!(column.offset(7, 2, "ratio"), None);
+    }
+
+    #[test]
+    fn test_format_rule() {
+        let rule =
+            FormatRule::parse("price", "< 0 => background #FFC7CE, color red, bold").unwrap();
+        assert_eq!(rule.target, Target::Column("price".to_owned()));
+        assert_eq!(
+            rule.condition,
+            Condition::Compare(Operator::Less, "0".to_owned())
+        );
+        assert_eq!(
+            rule.style,
+            Style {
+                background: Some(0xFFC7CE),
+                foreground: Some(0xC00000),
+                bold: true,
+                italic: false,
+            }
+        );
+        assert_eq!(
+            rule.to_string(),
+            "< 0 => background #FFC7CE, color #C00000, bold"
+        );
+
+        let rule = FormatRule::parse("text", "~ \"to do\" => italic").unwrap();
+        assert_eq!(
+            rule.condition,
+            Condition::Compare(Operator::Contains, "to do".to_owned())
+        );
+        let rule = FormatRule::parse("B2:B9", "formula =A2 >= 10 => bold").unwrap();
+        assert_eq!(rule.to_string(), "formula =A2 >= 10 => bold");
+        let rule = FormatRule::parse("ratio", "scale red #00FF00").unwrap();
+        assert_eq!(rule.condition, Condition::Scale(0xC00000, 0x00FF00));
+        assert_eq!(rule.to_string(), "scale #C00000 #00FF00");
+
+        assert!(FormatRule::parse("A", "> 1").is_err());
+        assert!(FormatRule::parse("A", "1 => bold").is_err());
+        assert!(FormatRule::parse("A", "> 1 => background pink").is_err());
+        assert!(FormatRule::parse("A", "> 1 => bold 2").is_err());
+        assert!(FormatRule::parse("A", "scale red").is_err());
+        assert!(FormatRule::parse("A", "formula =SUM( => bold").is_err());
+    }
+
+    #[test]
+    fn test_colors() {
+        assert_eq!(parse_color("#63be7b"), Some(0x63BE7B));
+        assert_eq!(parse_color("Green"), Some(0x00B050));
+        assert_eq!(parse_color("#FFF"), None);
+        assert_eq!(blend(0x000000, 0xFF8040, 0.5), 0x804020);
+        assert_eq!(blend(0x102030, 0xFFFFFF, -1.0), 0x102030);
+        assert_eq!(blend(0x102030, 0xFFFFFF, 2.0), 0xFFFFFF);
+    }
+}
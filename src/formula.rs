@@ -0,0 +1,853 @@
+use std::fmt;
+
+use crate::draw_table::{cell_name, parse_column_label, MAX_LABELED_COLUMNS};
+use crate::error::Error;
+use crate::value::Value;
+
+// Sheet position of a referenced cell, `A1` is row 0 of column 0
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CellRef {
+    pub row: usize,
+    pub col: usize,
+}
+
+impl CellRef {
+    // A column label followed by the row number, e.g. `B3` or `AA12`
+    pub fn parse(label: &str) -> Option<CellRef> {
+        let digits = label.find(|c: char| c.is_ascii_digit())?;
+        let col = parse_column_label(&label[..digits].to_ascii_uppercase())?;
+        let row: usize = label[digits..].parse().ok()?;
+        if row == 0 || !label[digits..].chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        Some(CellRef { row: row - 1, col })
+    }
+}
+
+impl fmt::Display for CellRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", cell_name(self.row, self.col))
+    }
+}
+
+// A cell or a range a formula refers to, by its top left and bottom right corners
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Reference {
+    pub first: CellRef,
+    pub last: CellRef,
+}
+
+impl Reference {
+    pub fn cell(cell: CellRef) -> Reference {
+        Reference {
+            first: cell,
+            last: cell,
+        }
+    }
+
+    pub fn range(start: CellRef, end: CellRef) -> Reference {
+        Reference {
+            first: CellRef {
+                row: start.row.min(end.row),
+                col: start.col.min(end.col),
+            },
+            last: CellRef {
+                row: start.row.max(end.row),
+                col: start.col.max(end.col),
+            },
+        }
+    }
+
+    pub fn is_cell(&self) -> bool {
+        self.first == self.last
+    }
+
+    pub fn contains(&self, cell: CellRef) -> bool {
+        (self.first.row..=self.last.row).contains(&cell.row)
+            && (self.first.col..=self.last.col).contains(&cell.col)
+    }
+}
+
+impl fmt::Display for Reference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.is_cell() {
+            true => write!(f, "{}", self.first),
+            false => write!(f, "{}:{}", self.first, self.last),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Operator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Power,
+    Concat,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Function {
+    Sum,
+    Average,
+    Min,
+    Max,
+    Count,
+    If,
+    Concat,
+    Len,
+}
+
+impl Function {
+    pub fn from_name(name: &str) -> Option<Function> {
+        match name.to_uppercase().as_str() {
+            "SUM" => Some(Function::Sum),
+            "AVERAGE" => Some(Function::Average),
+            "MIN" => Some(Function::Min),
+            "MAX" => Some(Function::Max),
+            "COUNT" => Some(Function::Count),
+            "IF" => Some(Function::If),
+            "CONCAT" => Some(Function::Concat),
+            "LEN" => Some(Function::Len),
+            _ => None,
+        }
+    }
+
+    // Smallest and largest number of arguments
+    fn arity(&self) -> (usize, usize) {
+        match self {
+            Function::If => (2, 3),
+            Function::Len => (1, 1),
+            _ => (1, usize::MAX),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Number(f64),
+    Text(String),
+    Boolean(bool),
+    Ref(CellRef),
+    // Corners of a rectangle of cells, only allowed as function arguments
+    Range(CellRef, CellRef),
+    Negate(Box<Expr>),
+    Binary(Operator, Box<Expr>, Box<Expr>),
+    Call(Function, Vec<Expr>),
+}
+
+impl Expr {
+    // Cells and ranges the expression refers to, a range stays one reference however many
+    // cells it has
+    pub fn references(&self) -> Vec<Reference> {
+        let mut references = Vec::new();
+        self.collect_references(&mut references);
+        references
+    }
+
+    fn collect_references(&self, references: &mut Vec<Reference>) {
+        match self {
+            Expr::Ref(reference) => references.push(Reference::cell(*reference)),
+            Expr::Range(start, end) => references.push(Reference::range(*start, *end)),
+            Expr::Negate(operand) => operand.collect_references(references),
+            Expr::Binary(_, left, right) => {
+                left.collect_references(references);
+                right.collect_references(references);
+            }
+            Expr::Call(_, arguments) => arguments
+                .iter()
+                .for_each(|argument| argument.collect_references(references)),
+            Expr::Number(_) | Expr::Text(_) | Expr::Boolean(_) => (),
+        }
+    }
+}
+
+// Shown in place of the value of a formula that can't be computed
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CellError {
+    DivideByZero,
+    // An operand of the wrong type, e.g. text in arithmetic
+    Value,
+    // A column that doesn't exist
+    Ref,
+    // The formula refers to itself, directly or through other formulas
+    Cycle,
+}
+
+impl fmt::Display for CellError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CellError::DivideByZero => write!(f, "#DIV/0!"),
+            CellError::Value => write!(f, "#VALUE!"),
+            CellError::Ref => write!(f, "#REF!"),
+            CellError::Cycle => write!(f, "#CYCLE!"),
+        }
+    }
+}
+
+pub type CellResult = Result<Value, CellError>;
+
+// Cells starting with `=` hold a formula
+pub fn is_formula(input: &str) -> bool {
+    input.starts_with('=')
+}
+
+// The formula with its cell references moved by `rows` and `cols`, like filling or copying
+// it to another cell does. None if a reference would leave the sheet.
+pub fn shift_references(source: &str, rows: isize, cols: isize) -> Option<String> {
+    let mut shifted = String::with_capacity(source.len());
+    let mut quoted = false;
+    let mut index = 0;
+    while let Some(c) = source[index..].chars().next() {
+        // Names start after anything but letters, digits and the dot of a number
+        let starts_name = !source[..index]
+            .ends_with(|previous: char| previous.is_ascii_alphanumeric() || previous == '.');
+        if c == '"' {
+            quoted = !quoted;
+        } else if !quoted && c.is_ascii_alphabetic() && starts_name {
+            let end = source[index..]
+                .find(|c: char| !c.is_ascii_alphanumeric())
+                .map_or(source.len(), |end| index + end);
+            let name = &source[index..end];
+            let function = source[end..].trim_start().starts_with('(');
+            match CellRef::parse(name) {
+                Some(cell) if !function => {
+                    let row = cell.row as isize + rows;
+                    let col = cell.col as isize + cols;
+                    if row < 0 || col < 0 || col as usize >= MAX_LABELED_COLUMNS {
+                        return None;
+                    }
+                    shifted.push_str(&cell_name(row as usize, col as usize));
+                }
+                _ => shifted.push_str(name),
+            }
+            index = end;
+            continue;
+        }
+        shifted.push(c);
+        index += c.len_utf8();
+    }
+    Some(shifted)
+}
+
+// The formula of a cell and what it computed to the last time
+#[derive(Debug, Clone, PartialEq)]
+pub struct Formula {
+    // As typed, including the `=`
+    pub source: String,
+    pub expr: Expr,
+    pub result: CellResult,
+}
+
+impl Formula {
+    pub fn parse(source: &str) -> Result<Formula, Error> {
+        let input = source
+            .strip_prefix('=')
+            .ok_or_else(|| Error::new("A formula starts with \"=\""))?;
+        let mut parser = Parser {
+            tokens: tokenize(input)?,
+            position: 0,
+        };
+        let expr = parser.comparison()?;
+        if let Some(token) = parser.peek() {
+            return Err(Error::new(&format!("Unexpected {}", token)));
+        }
+        Ok(Formula {
+            source: source.to_owned(),
+            expr,
+            result: Ok(Value::Null),
+        })
+    }
+
+    // The result shown in the cell, errors as their code
+    pub fn display_value(&self) -> Value {
+        match &self.result {
+            Ok(value) => value.clone(),
+            Err(error) => Value::Text(error.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Text(String),
+    Name(String),
+    Operator(&'static str),
+    Open,
+    Close,
+    Comma,
+    Colon,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Number(number) => write!(f, "{}", number),
+            Token::Text(text) => write!(f, "\"{}\"", text),
+            Token::Name(name) => write!(f, "{}", name),
+            Token::Operator(operator) => write!(f, "\"{}\"", operator),
+            Token::Open => write!(f, "\"(\""),
+            Token::Close => write!(f, "\")\""),
+            Token::Comma => write!(f, "\",\""),
+            Token::Colon => write!(f, "\":\""),
+        }
+    }
+}
+
+// Longer operators first, so `<=` isn't read as `<`
+const OPERATORS: [&str; 12] = [
+    "<>", "<=", ">=", "+", "-", "*", "/", "^", "&", "=", "<", ">",
+];
+
+fn tokenize(input: &str) -> Result<Vec<Token>, Error> {
+    let mut tokens = Vec::new();
+    let mut rest = input.trim_start();
+    while let Some(c) = rest.chars().next() {
+        if c.is_ascii_digit() || c == '.' {
+            let end = rest
+                .find(|c: char| !c.is_ascii_digit() && c != '.')
+                .unwrap_or(rest.len());
+            let number = rest[..end]
+                .parse()
+                .map_err(|_| Error::new(&format!("Invalid number \"{}\"", &rest[..end])))?;
+            tokens.push(Token::Number(number));
+            rest = &rest[end..];
+        } else if c.is_ascii_alphabetic() {
+            let end = rest
+                .find(|c: char| !c.is_ascii_alphanumeric())
+                .unwrap_or(rest.len());
+            tokens.push(Token::Name(rest[..end].to_owned()));
+            rest = &rest[end..];
+        } else if c == '"' {
+            // Doubled quotes stand for one
+            let mut text = String::new();
+            let mut chars = rest[1..].char_indices();
+            let end = loop {
+                match chars.next() {
+                    Some((index, '"')) if rest[1 + index + 1..].starts_with('"') => {
+                        text.push('"');
+                        chars.next();
+                    }
+                    Some((index, '"')) => break 1 + index + 1,
+                    Some((_, c)) => text.push(c),
+                    None => return Err(Error::new("Text is not closed")),
+                }
+            };
+            tokens.push(Token::Text(text));
+            rest = &rest[end..];
+        } else {
+            let token = match c {
+                '(' => Token::Open,
+                ')' => Token::Close,
+                ',' | ';' => Token::Comma,
+                ':' => Token::Colon,
+                _ => match OPERATORS
+                    .iter()
+                    .find(|operator| rest.starts_with(*operator))
+                {
+                    Some(operator) => Token::Operator(operator),
+                    None => return Err(Error::new(&format!("Unexpected \"{}\"", c))),
+                },
+            };
+            let length = match token {
+                Token::Operator(operator) => operator.len(),
+                _ => c.len_utf8(),
+            };
+            tokens.push(token);
+            rest = &rest[length..];
+        }
+        rest = rest.trim_start();
+    }
+    Ok(tokens)
+}
+
+// Recursive descent, from the lowest precedence up: comparisons, `&`, `+ -`, `* /`, `^`,
+// unary minus
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), Error> {
+        match self.next() {
+            Some(token) if token == expected => Ok(()),
+            Some(token) => Err(Error::new(&format!("Expected {}, got {}", expected, token))),
+            None => Err(Error::new(&format!("Expected {}", expected))),
+        }
+    }
+
+    // Left associative binary operators of one precedence level
+    fn binary(
+        &mut self,
+        operators: &[(&str, Operator)],
+        operand: fn(&mut Parser) -> Result<Expr, Error>,
+    ) -> Result<Expr, Error> {
+        let mut left = operand(self)?;
+        loop {
+            let operator = match self.peek() {
+                Some(Token::Operator(symbol)) => operators
+                    .iter()
+                    .find(|(known, _)| known == symbol)
+                    .map(|(_, operator)| *operator),
+                _ => None,
+            };
+            match operator {
+                Some(operator) => {
+                    self.position += 1;
+                    let right = operand(self)?;
+                    left = Expr::Binary(operator, Box::new(left), Box::new(right));
+                }
+                None => return Ok(left),
+            }
+        }
+    }
+
+    fn comparison(&mut self) -> Result<Expr, Error> {
+        self.binary(
+            &[
+                ("=", Operator::Equal),
+                ("<>", Operator::NotEqual),
+                ("<", Operator::Less),
+                ("<=", Operator::LessEqual),
+                (">", Operator::Greater),
+                (">=", Operator::GreaterEqual),
+            ],
+            Parser::concat,
+        )
+    }
+
+    fn concat(&mut self) -> Result<Expr, Error> {
+        self.binary(&[("&", Operator::Concat)], Parser::sum)
+    }
+
+    fn sum(&mut self) -> Result<Expr, Error> {
+        self.binary(
+            &[("+", Operator::Add), ("-", Operator::Subtract)],
+            Parser::product,
+        )
+    }
+
+    fn product(&mut self) -> Result<Expr, Error> {
+        self.binary(
+            &[("*", Operator::Multiply), ("/", Operator::Divide)],
+            Parser::power,
+        )
+    }
+
+    fn power(&mut self) -> Result<Expr, Error> {
+        self.binary(&[("^", Operator::Power)], Parser::unary)
+    }
+
+    fn unary(&mut self) -> Result<Expr, Error> {
+        match self.peek() {
+            Some(Token::Operator("-")) => {
+                self.position += 1;
+                Ok(Expr::Negate(Box::new(self.unary()?)))
+            }
+            Some(Token::Operator("+")) => {
+                self.position += 1;
+                self.unary()
+            }
+            _ => self.primary(),
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr, Error> {
+        match self.next() {
+            Some(Token::Number(number)) => Ok(Expr::Number(number)),
+            Some(Token::Text(text)) => Ok(Expr::Text(text)),
+            Some(Token::Open) => {
+                let expr = self.comparison()?;
+                self.expect(Token::Close)?;
+                Ok(expr)
+            }
+            Some(Token::Name(name)) if self.peek() == Some(&Token::Open) => {
+                let function = Function::from_name(&name)
+                    .ok_or_else(|| Error::new(&format!("Unknown function {}", name)))?;
+                self.position += 1;
+                let mut arguments = Vec::new();
+                if self.peek() != Some(&Token::Close) {
+                    loop {
+                        arguments.push(self.argument()?);
+                        if self.peek() != Some(&Token::Comma) {
+                            break;
+                        }
+                        self.position += 1;
+                    }
+                }
+                self.expect(Token::Close)?;
+                let (min, max) = function.arity();
+                if arguments.len() < min || arguments.len() > max {
+                    return Err(Error::new(&format!(
+                        "Wrong number of arguments for {}",
+                        name.to_uppercase()
+                    )));
+                }
+                Ok(Expr::Call(function, arguments))
+            }
+            Some(Token::Name(name)) => match name.to_uppercase().as_str() {
+                "TRUE" => Ok(Expr::Boolean(true)),
+                "FALSE" => Ok(Expr::Boolean(false)),
+                _ => CellRef::parse(&name)
+                    .map(Expr::Ref)
+                    .ok_or_else(|| Error::new(&format!("Invalid cell reference {}", name))),
+            },
+            Some(token) => Err(Error::new(&format!("Unexpected {}", token))),
+            None => Err(Error::new("Unexpected end of formula")),
+        }
+    }
+
+    // Arguments can be ranges like `A1:B10`
+    fn argument(&mut self) -> Result<Expr, Error> {
+        let expr = self.comparison()?;
+        match expr {
+            Expr::Ref(start) if self.peek() == Some(&Token::Colon) => {
+                self.position += 1;
+                match self.next() {
+                    Some(Token::Name(name)) => match CellRef::parse(&name) {
+                        Some(end) => Ok(Expr::Range(start, end)),
+                        None => Err(Error::new(&format!("Invalid cell reference {}", name))),
+                    },
+                    _ => Err(Error::new("Expected the end of the range")),
+                }
+            }
+            expr => Ok(expr),
+        }
+    }
+}
+
+// Whole numbers are integers, so `=2*3` shows 6 and fits integer columns
+fn number_value(number: f64) -> CellResult {
+    if !number.is_finite() {
+        Err(CellError::Value)
+    } else if number.fract() == 0.0 && number.abs() < 1e15 {
+        Ok(Value::Integer(number as i64))
+    } else {
+        Ok(Value::Float(number))
+    }
+}
+
+// Empty cells count as 0, text only if it's a number
+fn to_number(value: &Value) -> Result<f64, CellError> {
+    match value {
+        Value::Null => Ok(0.0),
+        Value::Text(text) => text.trim().parse().map_err(|_| CellError::Value),
+        other => other.as_f64().ok_or(CellError::Value),
+    }
+}
+
+pub fn to_boolean(value: &Value) -> Result<bool, CellError> {
+    match value {
+        Value::Text(text) if text.eq_ignore_ascii_case("true") => Ok(true),
+        Value::Text(text) if text.eq_ignore_ascii_case("false") => Ok(false),
+        other => to_number(other).map(|number| number != 0.0),
+    }
+}
+
+// Numbers by value, anything else as text ignoring case
+fn compare(left: &Value, right: &Value) -> std::cmp::Ordering {
+    let number = |value: &Value| match value {
+        Value::Null | Value::Text(_) => None,
+        other => other.as_f64(),
+    };
+    match (number(left), number(right)) {
+        (Some(left), Some(right)) => left
+            .partial_cmp(&right)
+            .unwrap_or(std::cmp::Ordering::Equal),
+        _ => left
+            .to_string()
+            .to_lowercase()
+            .cmp(&right.to_string().to_lowercase()),
+    }
+}
+
+// `cell` gives the value of a referenced cell, computing it first if it's a formula.
+// `size` is the number of rows and columns of the sheet, ranges end there.
+pub fn evaluate(
+    expr: &Expr,
+    size: (usize, usize),
+    cell: &mut dyn FnMut(CellRef) -> CellResult,
+) -> CellResult {
+    match expr {
+        Expr::Number(number) => number_value(*number),
+        Expr::Text(text) => Ok(Value::Text(text.clone())),
+        Expr::Boolean(value) => Ok(Value::Boolean(*value)),
+        Expr::Ref(reference) => cell(*reference),
+        Expr::Range(_, _) => Err(CellError::Value),
+        Expr::Negate(operand) => number_value(-to_number(&evaluate(operand, size, cell)?)?),
+        Expr::Binary(operator, left, right) => {
+            let left = evaluate(left, size, cell)?;
+            let right = evaluate(right, size, cell)?;
+            binary(*operator, &left, &right)
+        }
+        Expr::Call(function, arguments) => call(*function, arguments, size, cell),
+    }
+}
+
+fn binary(operator: Operator, left: &Value, right: &Value) -> CellResult {
+    use std::cmp::Ordering::{Equal, Greater, Less};
+    let ordering = compare(left, right);
+    let boolean = |value| Ok(Value::Boolean(value));
+    match operator {
+        Operator::Concat => Ok(Value::Text(format!("{}{}", left, right))),
+        Operator::Equal => boolean(ordering == Equal),
+        Operator::NotEqual => boolean(ordering != Equal),
+        Operator::Less => boolean(ordering == Less),
+        Operator::LessEqual => boolean(ordering != Greater),
+        Operator::Greater => boolean(ordering == Greater),
+        Operator::GreaterEqual => boolean(ordering != Less),
+        _ => {
+            let (left, right) = (to_number(left)?, to_number(right)?);
+            match operator {
+                Operator::Add => number_value(left + right),
+                Operator::Subtract => number_value(left - right),
+                Operator::Multiply => number_value(left * right),
+                Operator::Divide if right == 0.0 => Err(CellError::DivideByZero),
+                Operator::Divide => number_value(left / right),
+                _ => number_value(left.powf(right)),
+            }
+        }
+    }
+}
+
+// Values of an argument, every cell of a range. `from_range` tells them apart, functions
+// skip text and empty cells in ranges like spreadsheets do.
+fn argument_values(
+    argument: &Expr,
+    (n_rows, n_cols): (usize, usize),
+    cell: &mut dyn FnMut(CellRef) -> CellResult,
+) -> Result<Vec<(Value, bool)>, CellError> {
+    match argument {
+        // Cells past the last row are empty and skipped, so they are not read at all
+        Expr::Range(start, end) => {
+            let cols = start.col.min(end.col)..=start.col.max(end.col);
+            if *cols.end() >= n_cols {
+                return Err(CellError::Ref);
+            }
+            let rows = start.row.min(end.row)..n_rows.min(start.row.max(end.row) + 1);
+            let mut values = Vec::new();
+            for row in rows {
+                for col in cols.clone() {
+                    values.push((cell(CellRef { row, col })?, true));
+                }
+            }
+            Ok(values)
+        }
+        other => Ok(vec![(evaluate(other, (n_rows, n_cols), cell)?, false)]),
+    }
+}
+
+fn call(
+    function: Function,
+    arguments: &[Expr],
+    size: (usize, usize),
+    cell: &mut dyn FnMut(CellRef) -> CellResult,
+) -> CellResult {
+    // Only the branch that is taken is computed
+    if function == Function::If {
+        let condition = to_boolean(&evaluate(&arguments[0], size, cell)?)?;
+        return match (condition, arguments.get(2)) {
+            (true, _) => evaluate(&arguments[1], size, cell),
+            (false, Some(otherwise)) => evaluate(otherwise, size, cell),
+            (false, None) => Ok(Value::Boolean(false)),
+        };
+    }
+
+    let mut values = Vec::new();
+    for argument in arguments {
+        values.extend(argument_values(argument, size, cell)?);
+    }
+    if function == Function::Concat {
+        return Ok(Value::Text(
+            values.iter().map(|(value, _)| value.to_string()).collect(),
+        ));
+    }
+    if function == Function::Len {
+        return number_value(values[0].0.to_string().chars().count() as f64);
+    }
+
+    let mut numbers = Vec::with_capacity(values.len());
+    for (value, from_range) in values.iter() {
+        match value {
+            Value::Null | Value::Text(_) if *from_range => (),
+            value => numbers.push(to_number(value)?),
+        }
+    }
+    let sum: f64 = numbers.iter().sum();
+    match function {
+        Function::Sum => number_value(sum),
+        Function::Average if numbers.is_empty() => Err(CellError::DivideByZero),
+        Function::Average => number_value(sum / numbers.len() as f64),
+        // Like spreadsheets, 0 if there are no numbers
+        Function::Min | Function::Max if numbers.is_empty() => number_value(0.0),
+        Function::Min => number_value(numbers.iter().copied().fold(f64::INFINITY, f64::min)),
+        Function::Max => number_value(numbers.iter().copied().fold(f64::NEG_INFINITY, f64::max)),
+        _ => number_value(numbers.len() as f64),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::formula::{
+        evaluate, shift_references, CellError, CellRef, CellResult, Expr, Formula, Reference,
+    };
+    use crate::value::Value;
+
+    // A 3x3 sheet: numbers in A, text in B, C is empty
+    fn cell(reference: CellRef) -> CellResult {
+        match (reference.col, reference.row) {
+            (0, row) if row < 3 => Ok(Value::Integer(row as i64 + 1)),
+            (1, 0) => Ok(Value::Text("ab".to_owned())),
+            (1, 1) => Ok(Value::Text("2.5".to_owned())),
+            (col, _) if col < 3 => Ok(Value::Null),
+            _ => Err(CellError::Ref),
+        }
+    }
+
+    fn compute(source: &str) -> CellResult {
+        let formula = Formula::parse(source).unwrap();
+        evaluate(&formula.expr, (3, 3), &mut cell)
+    }
+
+    #[test]
+    fn test_parse_formulas() {
+        assert_eq!(CellRef::parse("b12"), Some(CellRef { row: 11, col: 1 }));
+        assert_eq!(CellRef::parse("A0"), None);
+        assert_eq!(CellRef::parse("aa1"), Some(CellRef { row: 0, col: 26 }));
+        assert_eq!(
+            CellRef::parse("XFD2"),
+            Some(CellRef {
+                row: 1,
+                col: 16_383
+            })
+        );
+        assert_eq!(CellRef::parse("XFE1"), None);
+        assert_eq!(CellRef::parse("12"), None);
+        assert_eq!(CellRef { row: 2, col: 0 }.to_string(), "A3");
+
+        let formula = Formula::parse("=SUM(A1:A3, 2)").unwrap();
+        let references: Vec<String> = formula
+            .expr
+            .references()
+            .iter()
+            .map(Reference::to_string)
+            .collect();
+        assert_eq!(references, vec!["A1:A3"]);
+        let ranges = Formula::parse("=B9 + SUM(C5:A1)").unwrap();
+        let references: Vec<String> = ranges
+            .expr
+            .references()
+            .iter()
+            .map(Reference::to_string)
+            .collect();
+        assert_eq!(references, vec!["B9", "A1:C5"]);
+        assert_eq!(
+            formula.expr,
+            Expr::Call(
+                crate::formula::Function::Sum,
+                vec![
+                    Expr::Range(CellRef { row: 0, col: 0 }, CellRef { row: 2, col: 0 }),
+                    Expr::Number(2.0),
+                ]
+            )
+        );
+
+        for invalid in &[
+            "A1",
+            "=",
+            "=1 +",
+            "=(1",
+            "=FOO(1)",
+            "=LEN(A1, A2)",
+            "=IF(1)",
+            "=\"open",
+            "=1 2",
+            "=A1:A2",
+            "=1.2.3",
+            "=$A$1",
+        ] {
+            assert!(Formula::parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_shift_references() {
+        assert_eq!(
+            shift_references("=A1 * 2 + SUM(b2:B3)", 2, 1).unwrap(),
+            "=B3 * 2 + SUM(C4:C5)"
+        );
+        // Functions, text and numbers stay
+        assert_eq!(
+            shift_references("=LEN(\"A1\") + 2.5 * a1", 1, 0).unwrap(),
+            "=LEN(\"A1\") + 2.5 * A2"
+        );
+        assert_eq!(shift_references("=A2 - B1", -1, -1), None);
+    }
+
+    #[test]
+    fn test_evaluate_formulas() {
+        assert_eq!(compute("=1 + 2 * 3 - 4 / 8"), Ok(Value::Float(6.5)));
+        assert_eq!(compute("=(1 + 2) * 3 ^ 2"), Ok(Value::Integer(27)));
+        assert_eq!(compute("=-A2 * -A3"), Ok(Value::Integer(6)));
+        assert_eq!(compute("=A1 + B2 + C1"), Ok(Value::Float(3.5)));
+        assert_eq!(
+            compute("=B1 & \" \"\"x\"\" \" & A1"),
+            Ok(Value::Text("ab \"x\" 1".to_owned()))
+        );
+        assert_eq!(compute("=A1 < A2"), Ok(Value::Boolean(true)));
+        assert_eq!(compute("=b1 = \"AB\""), Ok(Value::Boolean(true)));
+        assert_eq!(compute("=A3 <> 3"), Ok(Value::Boolean(false)));
+
+        // Text and empty cells in ranges are skipped, arguments must be numbers
+        assert_eq!(compute("=SUM(A1:C3)"), Ok(Value::Integer(6)));
+        assert_eq!(compute("=sum(A1:A3, 4)"), Ok(Value::Integer(10)));
+        assert_eq!(compute("=AVERAGE(A1:A3)"), Ok(Value::Integer(2)));
+        assert_eq!(compute("=MIN(A3:A1)"), Ok(Value::Integer(1)));
+        assert_eq!(compute("=MAX(A1:A3, 0.5)"), Ok(Value::Integer(3)));
+        assert_eq!(compute("=COUNT(A1:C3)"), Ok(Value::Integer(3)));
+        assert_eq!(compute("=SUM(B1)"), Err(CellError::Value));
+
+        assert_eq!(
+            compute("=IF(A1 > 1, 1 / 0, \"small\")"),
+            Ok(Value::Text("small".to_owned()))
+        );
+        assert_eq!(compute("=IF(C1, 1)"), Ok(Value::Boolean(false)));
+        assert_eq!(
+            compute("=CONCAT(B1:B2, \"-\", A1)"),
+            Ok(Value::Text("ab2.5-1".to_owned()))
+        );
+        assert_eq!(compute("=LEN(B1 & A3)"), Ok(Value::Integer(3)));
+
+        assert_eq!(compute("=A1 / C1"), Err(CellError::DivideByZero));
+        assert_eq!(compute("=AVERAGE(C1:C3)"), Err(CellError::DivideByZero));
+        assert_eq!(compute("=B1 * 2"), Err(CellError::Value));
+        assert_eq!(compute("=SUM(A1:D1)"), Err(CellError::Ref));
+        // Only the rows of the sheet are read
+        assert_eq!(compute("=SUM(A2:A100000000)"), Ok(Value::Integer(5)));
+        assert_eq!(compute("=COUNT(C100000000:A1)"), Ok(Value::Integer(3)));
+        assert_eq!(compute("=MAX(C1:C3)"), Ok(Value::Integer(0)));
+
+        let mut formula = Formula::parse("=1/0").unwrap();
+        formula.result = compute(&formula.source);
+        assert_eq!(formula.display_value(), Value::Text("#DIV/0!".to_owned()));
+    }
+}
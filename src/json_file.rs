@@ -0,0 +1,190 @@
+use std::io::{BufRead, Write};
+
+use mysql::serde_json::{self, Map, Value as JsonValue};
+
+use crate::csv::Record;
+use crate::database::{Column, Row, SYSTEM_COLUMNS};
+use crate::error::Error;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum JsonFormat {
+    // A single array of objects
+    Array,
+    // One object per line (NDJSON)
+    Lines,
+}
+
+impl JsonFormat {
+    pub fn from_path(path: &str) -> Option<JsonFormat> {
+        let path = path.to_lowercase();
+        if path.ends_with(".json") {
+            Some(JsonFormat::Array)
+        } else if path.ends_with(".ndjson") || path.ends_with(".jsonl") {
+            Some(JsonFormat::Lines)
+        } else {
+            None
+        }
+    }
+}
+
+// Keyed by column name, with the row id under `id`. Values are encoded like in the sync payload.
+pub fn row_object(columns: &[Column], row: &Row) -> JsonValue {
+    let mut object = Map::new();
+    object.insert("id".to_owned(), row.id.into());
+    for (column, value) in columns.iter().zip(row.values.iter()) {
+        object.insert(column.name.clone(), value.to_json());
+    }
+    JsonValue::Object(object)
+}
+
+// Rows are written one by one, nothing but the current row is built in memory
+pub fn write<W: Write>(
+    mut writer: W,
+    format: JsonFormat,
+    columns: &[Column],
+    rows: &[Row],
+) -> Result<(), Error> {
+    if format == JsonFormat::Array {
+        writer.write_all(b"[")?;
+    }
+    for (index, row) in rows.iter().enumerate() {
+        match format {
+            JsonFormat::Array if index > 0 => writer.write_all(b",\n  ")?,
+            JsonFormat::Array => writer.write_all(b"\n  ")?,
+            JsonFormat::Lines => (),
+        }
+        serde_json::to_writer(&mut writer, &row_object(columns, row))?;
+        if format == JsonFormat::Lines {
+            writer.write_all(b"\n")?;
+        }
+    }
+    if format == JsonFormat::Array {
+        writer.write_all(b"\n]\n")?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+// Values as text the way `ColumnType::decode_json` reads them
+fn field_text(value: &JsonValue) -> String {
+    match value {
+        JsonValue::Null => String::new(),
+        JsonValue::String(text) => text.clone(),
+        other => other.to_string(),
+    }
+}
+
+// Turns objects into records with a header of all field names the objects have.
+// Row ids are assigned by the database, so `id` and `version` fields are skipped.
+fn to_records(objects: Vec<(usize, JsonValue)>) -> Result<Vec<Record>, Error> {
+    let mut names: Vec<String> = Vec::new();
+    let mut records = vec![Record {
+        line: 0,
+        fields: Vec::new(),
+    }];
+    for (line, object) in objects {
+        let object = match object {
+            JsonValue::Object(object) => object,
+            _ => return Err(Error::new(&format!("Line {}: expected an object", line))),
+        };
+        let mut fields = vec![String::new(); names.len()];
+        for (name, value) in object.iter() {
+            if SYSTEM_COLUMNS.contains(&name.as_str()) {
+                continue;
+            }
+            let index = match names.iter().position(|known| known == name) {
+                Some(index) => index,
+                None => {
+                    names.push(name.clone());
+                    fields.push(String::new());
+                    names.len() - 1
+                }
+            };
+            fields[index] = field_text(value);
+        }
+        records.push(Record { line, fields });
+    }
+    records[0].fields = names;
+    Ok(records)
+}
+
+// The first record is the header. For NDJSON `line` is the line of the object,
+// for arrays its position in the array.
+pub fn read<R: BufRead>(reader: R, format: JsonFormat) -> Result<Vec<Record>, Error> {
+    let objects = match format {
+        JsonFormat::Array => match serde_json::from_reader(reader)? {
+            JsonValue::Array(objects) => objects
+                .into_iter()
+                .enumerate()
+                .map(|(index, object)| (index + 1, object))
+                .collect(),
+            _ => return Err(Error::new("Expected an array of objects")),
+        },
+        JsonFormat::Lines => {
+            let mut objects = Vec::new();
+            for (index, line) in reader.lines().enumerate() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let object = serde_json::from_str(&line)
+                    .map_err(|error| Error::new(&format!("Line {}: {}", index + 1, error)))?;
+                objects.push((index + 1, object));
+            }
+            objects
+        }
+    };
+    to_records(objects)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::{example_columns, example_rows};
+    use crate::json_file::{read, write, JsonFormat};
+    use crate::transfer::ImportPlan;
+    use crate::value::Value;
+    use mysql::serde_json::{self, json, Value as JsonValue};
+
+    #[test]
+    fn test_json_round_trip() {
+        let columns = example_columns();
+        let expected: Vec<Vec<Value>> = example_rows().into_iter().map(|row| row.values).collect();
+
+        for format in &[JsonFormat::Array, JsonFormat::Lines] {
+            let mut file = Vec::new();
+            write(&mut file, *format, &columns, &example_rows()).unwrap();
+            let records = read(&file[..], *format).unwrap();
+            let plan = ImportPlan::new(&records, Some(true), &columns, &[]);
+            assert!(plan.errors.is_empty(), "{:?}", plan.errors);
+            let values: Vec<Vec<Value>> = plan.rows.into_iter().map(|row| row.values).collect();
+            assert_eq!(values, expected, "{:?}", format);
+        }
+
+        let mut file = Vec::new();
+        write(&mut file, JsonFormat::Array, &columns, &example_rows()[..1]).unwrap();
+        let json: JsonValue = serde_json::from_slice(&file).unwrap();
+        assert_eq!(json[0]["id"], json!(1));
+        assert_eq!(json[0]["price"], json!("9.99"));
+        assert_eq!(json[0]["active"], json!(true));
+    }
+
+    #[test]
+    fn test_json_mapping() {
+        let columns = example_columns();
+        let file = "{\"id\": 7, \"amount\": 5, \"label\": \"five\"}\n\n{\"amount\": \"x\"}\n";
+        let records = read(file.as_bytes(), JsonFormat::Lines).unwrap();
+        assert_eq!(records[0].fields, vec!["amount", "label"]);
+
+        let mapping = vec![
+            ("amount".to_owned(), "number".to_owned()),
+            ("label".to_owned(), "text".to_owned()),
+        ];
+        let plan = ImportPlan::new(&records, Some(true), &columns, &mapping);
+        assert_eq!(plan.rows.len(), 1);
+        assert_eq!(plan.rows[0].values[0], Value::Integer(5));
+        assert_eq!(plan.rows[0].values[1], Value::Text("five".to_owned()));
+        assert_eq!(plan.errors[0].line, 3);
+
+        assert!(read("[1]".as_bytes(), JsonFormat::Array).is_err());
+    }
+}
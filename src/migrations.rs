@@ -0,0 +1,172 @@
+// Ordered schema changes, each one is applied once and recorded in `schema_migrations`.
+// Never edit an applied migration, append a new one instead.
+
+pub enum Step {
+    Sql(&'static str),
+    // Skipped if the column is already there, e.g. in tables created before migrations existed
+    AddColumn {
+        name: &'static str,
+        definition: &'static str,
+    },
+}
+
+pub struct Migration {
+    pub version: u32,
+    pub name: &'static str,
+    pub steps: &'static [Step],
+}
+
+pub const MIGRATIONS_TABLE: &str = r"CREATE TABLE IF NOT EXISTS schema_migrations (
+    version INT NOT NULL PRIMARY KEY,
+    name VARCHAR(255) NOT NULL,
+    applied_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+)";
+
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "create simple_table",
+        steps: &[Step::Sql(
+            r"CREATE TABLE IF NOT EXISTS simple_table (
+                id INT NOT NULL AUTO_INCREMENT PRIMARY KEY,
+                number INTEGER,
+                text TEXT
+            )",
+        )],
+    },
+    Migration {
+        version: 2,
+        name: "add typed example columns",
+        steps: &[
+            Step::AddColumn {
+                name: "price",
+                definition: "DECIMAL(10,2)",
+            },
+            Step::AddColumn {
+                name: "ratio",
+                definition: "DOUBLE",
+            },
+            Step::AddColumn {
+                name: "active",
+                definition: "BOOLEAN",
+            },
+            Step::AddColumn {
+                name: "day",
+                definition: "DATE",
+            },
+            Step::AddColumn {
+                name: "created",
+                definition: "DATETIME",
+            },
+        ],
+    },
+    Migration {
+        version: 3,
+        name: "add row version for optimistic locking",
+        steps: &[Step::AddColumn {
+            name: "version",
+            definition: "INT NOT NULL DEFAULT 0",
+        }],
+    },
+    Migration {
+        version: 4,
+        name: "record row changes for live refresh",
+        steps: &[
+            Step::Sql(
+                r"CREATE TABLE IF NOT EXISTS simple_table_changes (
+                    seq BIGINT NOT NULL AUTO_INCREMENT PRIMARY KEY,
+                    row_id INT NOT NULL,
+                    deleted BOOLEAN NOT NULL DEFAULT FALSE,
+                    changed_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+                )",
+            ),
+            // Writers that don't know about versions still invalidate other clients' copies
+            Step::Sql(r"DROP TRIGGER IF EXISTS simple_table_bump_version"),
+            Step::Sql(
+                r"CREATE TRIGGER simple_table_bump_version
+                BEFORE UPDATE ON simple_table FOR EACH ROW
+                BEGIN
+                    IF NEW.version = OLD.version THEN
+                        SET NEW.version = OLD.version + 1;
+                    END IF;
+                END",
+            ),
+            Step::Sql(r"DROP TRIGGER IF EXISTS simple_table_log_insert"),
+            Step::Sql(
+                r"CREATE TRIGGER simple_table_log_insert
+                AFTER INSERT ON simple_table FOR EACH ROW
+                INSERT INTO simple_table_changes (row_id) VALUES (NEW.id)",
+            ),
+            Step::Sql(r"DROP TRIGGER IF EXISTS simple_table_log_update"),
+            Step::Sql(
+                r"CREATE TRIGGER simple_table_log_update
+                AFTER UPDATE ON simple_table FOR EACH ROW
+                INSERT INTO simple_table_changes (row_id) VALUES (NEW.id)",
+            ),
+            Step::Sql(r"DROP TRIGGER IF EXISTS simple_table_log_delete"),
+            Step::Sql(
+                r"CREATE TRIGGER simple_table_log_delete
+                AFTER DELETE ON simple_table FOR EACH ROW
+                INSERT INTO simple_table_changes (row_id, deleted) VALUES (OLD.id, TRUE)",
+            ),
+        ],
+    },
+    Migration {
+        version: 5,
+        name: "audit cell changes",
+        steps: &[Step::Sql(
+            r"CREATE TABLE IF NOT EXISTS simple_table_audit (
+                seq BIGINT NOT NULL AUTO_INCREMENT PRIMARY KEY,
+                row_id INT NOT NULL,
+                action ENUM('insert', 'update', 'delete') NOT NULL,
+                column_name VARCHAR(64),
+                old_value TEXT,
+                new_value TEXT,
+                client VARCHAR(255) NOT NULL,
+                changed_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                INDEX (row_id, column_name)
+            )",
+        )],
+    },
+    Migration {
+        version: 6,
+        name: "store cell formulas",
+        steps: &[Step::Sql(
+            r"CREATE TABLE IF NOT EXISTS simple_table_formulas (
+                row_id INT NOT NULL,
+                column_name VARCHAR(64) NOT NULL,
+                formula TEXT NOT NULL,
+                PRIMARY KEY (row_id, column_name)
+            )",
+        )],
+    },
+    Migration {
+        version: 7,
+        name: "store column validation rules",
+        steps: &[Step::Sql(
+            r"CREATE TABLE IF NOT EXISTS simple_table_rules (
+                column_name VARCHAR(64) NOT NULL PRIMARY KEY,
+                rule TEXT NOT NULL
+            )",
+        )],
+    },
+    Migration {
+        version: 8,
+        name: "store conditional formats",
+        steps: &[Step::Sql(
+            r"CREATE TABLE IF NOT EXISTS simple_table_formats (
+                id INT AUTO_INCREMENT PRIMARY KEY,
+                target VARCHAR(64) NOT NULL,
+                format TEXT NOT NULL
+            )",
+        )],
+    },
+];
+
+pub fn latest_version() -> u32 {
+    MIGRATIONS
+        .iter()
+        .map(|migration| migration.version)
+        .max()
+        .unwrap_or(0)
+}
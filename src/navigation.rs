@@ -0,0 +1,274 @@
+// Keyboard movement of the selected cell
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Movement {
+    Up,
+    Down,
+    Left,
+    Right,
+    // Tab and Shift+Tab, wrapping to the next or previous row
+    Next,
+    Previous,
+    RowStart,
+    RowEnd,
+    SheetStart,
+    SheetEnd,
+    PageUp(usize),
+    PageDown(usize),
+}
+
+// Cell the movement leads to, staying inside the sheet
+pub fn move_cell(
+    (row, col): (usize, usize),
+    movement: Movement,
+    n_rows: usize,
+    n_cols: usize,
+) -> (usize, usize) {
+    if n_rows == 0 || n_cols == 0 {
+        return (0, 0);
+    }
+    let (last_row, last_col) = (n_rows - 1, n_cols - 1);
+    let (row, col) = (row.min(last_row), col.min(last_col));
+    match movement {
+        Movement::Up => (row.saturating_sub(1), col),
+        Movement::Down => ((row + 1).min(last_row), col),
+        Movement::Left => (row, col.saturating_sub(1)),
+        Movement::Right => (row, (col + 1).min(last_col)),
+        Movement::Next if col < last_col => (row, col + 1),
+        Movement::Next if row < last_row => (row + 1, 0),
+        Movement::Next => (row, col),
+        Movement::Previous if col > 0 => (row, col - 1),
+        Movement::Previous if row > 0 => (row - 1, last_col),
+        Movement::Previous => (row, col),
+        Movement::RowStart => (row, 0),
+        Movement::RowEnd => (row, last_col),
+        Movement::SheetStart => (0, 0),
+        Movement::SheetEnd => (last_row, last_col),
+        Movement::PageUp(page) => (row.saturating_sub(page.max(1)), col),
+        Movement::PageDown(page) => ((row + page.max(1)).min(last_row), col),
+    }
+}
+
+// Rectangular range of cells, from the anchor where selecting started to the cursor
+// that moves. The cursor is the cell edited by typing.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct Selection {
+    pub anchor: (usize, usize),
+    pub cursor: (usize, usize),
+}
+
+impl Selection {
+    pub fn cell(row: usize, col: usize) -> Selection {
+        Selection {
+            anchor: (row, col),
+            cursor: (row, col),
+        }
+    }
+
+    // Whole rows `from` to `to`, the cursor on the first column
+    pub fn rows(from: usize, to: usize, n_cols: usize) -> Selection {
+        Selection {
+            anchor: (from, n_cols.saturating_sub(1)),
+            cursor: (to, 0),
+        }
+    }
+
+    // Whole columns `from` to `to`, the cursor on the first row
+    pub fn columns(from: usize, to: usize, n_rows: usize) -> Selection {
+        Selection {
+            anchor: (n_rows.saturating_sub(1), from),
+            cursor: (0, to),
+        }
+    }
+
+    pub fn extend(&self, cursor: (usize, usize)) -> Selection {
+        Selection {
+            anchor: self.anchor,
+            cursor,
+        }
+    }
+
+    pub fn top_left(&self) -> (usize, usize) {
+        (
+            self.anchor.0.min(self.cursor.0),
+            self.anchor.1.min(self.cursor.1),
+        )
+    }
+
+    pub fn bottom_right(&self) -> (usize, usize) {
+        (
+            self.anchor.0.max(self.cursor.0),
+            self.anchor.1.max(self.cursor.1),
+        )
+    }
+
+    pub fn contains(&self, row: usize, col: usize) -> bool {
+        let (top, left) = self.top_left();
+        let (bottom, right) = self.bottom_right();
+        (top..=bottom).contains(&row) && (left..=right).contains(&col)
+    }
+
+    // The part inside a sheet of that size, None if nothing is left
+    pub fn clamp(&self, n_rows: usize, n_cols: usize) -> Option<Selection> {
+        if n_rows == 0 || n_cols == 0 {
+            return None;
+        }
+        let clamp = |(row, col): (usize, usize)| (row.min(n_rows - 1), col.min(n_cols - 1));
+        Some(Selection {
+            anchor: clamp(self.anchor),
+            cursor: clamp(self.cursor),
+        })
+    }
+}
+
+// Range the fill handle of `selection` covers when dragged to `(row, col)`: the selection
+// extended over rows or columns, whichever the cell is further out on
+pub fn fill_range(selection: Selection, (row, col): (usize, usize)) -> Selection {
+    let (top, left) = selection.top_left();
+    let (bottom, right) = selection.bottom_right();
+    let rows_out = row.saturating_sub(bottom) + top.saturating_sub(row);
+    let cols_out = col.saturating_sub(right) + left.saturating_sub(col);
+    let (top_left, bottom_right) = if rows_out == 0 && cols_out == 0 {
+        return selection;
+    } else if rows_out >= cols_out {
+        ((top.min(row), left), (bottom.max(row), right))
+    } else {
+        ((top, left.min(col)), (bottom, right.max(col)))
+    };
+    Selection {
+        anchor: bottom_right,
+        cursor: top_left,
+    }
+}
+
+// Row or column `offset` pixels past the start of `first`
+pub fn index_at(
+    first: usize,
+    count: usize,
+    offset: i32,
+    size: impl Fn(usize) -> i32,
+) -> Option<usize> {
+    let mut end = 0;
+    for index in first..count {
+        end += size(index);
+        if offset < end {
+            return Some(index);
+        }
+    }
+    None
+}
+
+// Number of rows or columns fully shown from `first` on, at least one
+pub fn visible_count(first: usize, count: usize, space: i32, size: impl Fn(usize) -> i32) -> usize {
+    let mut used = 0;
+    let mut visible = 0;
+    for index in first..count {
+        used += size(index);
+        if used > space {
+            break;
+        }
+        visible += 1;
+    }
+    visible.max(1)
+}
+
+// First row or column to show so that `target` is fully in view, scrolling as little
+// as possible from `first`
+pub fn scroll_position(
+    first: usize,
+    target: usize,
+    space: i32,
+    size: impl Fn(usize) -> i32,
+) -> usize {
+    if target <= first {
+        return target;
+    }
+    let mut first = first;
+    let mut used: i32 = (first..=target).map(&size).sum();
+    while used > space && first < target {
+        used -= size(first);
+        first += 1;
+    }
+    first
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::navigation::{
+        fill_range, index_at, move_cell, scroll_position, visible_count, Movement, Selection,
+    };
+
+    #[test]
+    fn test_move_cell() {
+        assert_eq!(move_cell((0, 0), Movement::Up, 5, 3), (0, 0));
+        assert_eq!(move_cell((0, 0), Movement::Down, 5, 3), (1, 0));
+        assert_eq!(move_cell((4, 2), Movement::Down, 5, 3), (4, 2));
+        assert_eq!(move_cell((1, 0), Movement::Left, 5, 3), (1, 0));
+        assert_eq!(move_cell((1, 2), Movement::Right, 5, 3), (1, 2));
+        assert_eq!(move_cell((1, 1), Movement::Next, 5, 3), (1, 2));
+        assert_eq!(move_cell((1, 2), Movement::Next, 5, 3), (2, 0));
+        assert_eq!(move_cell((4, 2), Movement::Next, 5, 3), (4, 2));
+        assert_eq!(move_cell((2, 0), Movement::Previous, 5, 3), (1, 2));
+        assert_eq!(move_cell((0, 0), Movement::Previous, 5, 3), (0, 0));
+        assert_eq!(move_cell((3, 1), Movement::RowStart, 5, 3), (3, 0));
+        assert_eq!(move_cell((3, 1), Movement::RowEnd, 5, 3), (3, 2));
+        assert_eq!(move_cell((3, 1), Movement::SheetStart, 5, 3), (0, 0));
+        assert_eq!(move_cell((3, 1), Movement::SheetEnd, 5, 3), (4, 2));
+        assert_eq!(move_cell((3, 1), Movement::PageUp(2), 5, 3), (1, 1));
+        assert_eq!(move_cell((3, 1), Movement::PageDown(2), 5, 3), (4, 1));
+        assert_eq!(move_cell((3, 1), Movement::PageDown(0), 5, 3), (4, 1));
+        // Rows were deleted under the selection
+        assert_eq!(move_cell((9, 1), Movement::Up, 5, 3), (3, 1));
+        assert_eq!(move_cell((2, 2), Movement::Down, 0, 3), (0, 0));
+    }
+
+    #[test]
+    fn test_scrolling() {
+        let widths = [100, 50, 200, 100];
+        let width = |col: usize| widths[col];
+        assert_eq!(visible_count(0, 4, 170, width), 2);
+        assert_eq!(visible_count(2, 4, 100, width), 1);
+        assert_eq!(visible_count(0, 100, 270, |_| 27), 10);
+
+        assert_eq!(scroll_position(2, 1, 300, width), 1);
+        assert_eq!(scroll_position(0, 1, 300, width), 0);
+        assert_eq!(scroll_position(0, 2, 300, width), 1);
+        assert_eq!(scroll_position(0, 3, 300, width), 2);
+        // Wider than the table, shown from its start
+        assert_eq!(scroll_position(0, 2, 150, width), 2);
+
+        assert_eq!(index_at(0, 4, 120, width), Some(1));
+        assert_eq!(index_at(1, 4, 120, width), Some(2));
+        assert_eq!(index_at(0, 4, 450, width), None);
+    }
+
+    #[test]
+    fn test_selection() {
+        let selection = Selection::cell(3, 2).extend((1, 4));
+        assert_eq!(selection.top_left(), (1, 2));
+        assert_eq!(selection.bottom_right(), (3, 4));
+        assert!(selection.contains(2, 3));
+        assert!(selection.contains(1, 4));
+        assert!(!selection.contains(0, 3));
+        assert!(!selection.contains(2, 5));
+
+        let rows = Selection::rows(4, 2, 3);
+        assert_eq!((rows.top_left(), rows.bottom_right()), ((2, 0), (4, 2)));
+        assert_eq!(rows.cursor, (2, 0));
+        let columns = Selection::columns(1, 1, 5);
+        assert_eq!(
+            (columns.top_left(), columns.bottom_right()),
+            ((0, 1), (4, 1))
+        );
+
+        assert_eq!(selection.clamp(2, 3), Some(Selection::cell(1, 2)));
+        assert_eq!(selection.clamp(0, 3), None);
+
+        // B2:C3 dragged down, left and inside
+        let selection = Selection::cell(1, 1).extend((2, 2));
+        let range = |selection: Selection| (selection.top_left(), selection.bottom_right());
+        assert_eq!(range(fill_range(selection, (5, 3))), ((1, 1), (5, 2)));
+        assert_eq!(range(fill_range(selection, (2, 0))), ((1, 0), (2, 2)));
+        assert_eq!(range(fill_range(selection, (0, 1))), ((0, 1), (2, 2)));
+        assert_eq!(fill_range(selection, (2, 1)), selection);
+    }
+}
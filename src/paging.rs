@@ -0,0 +1,194 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use crate::database::{Column, Database, MySqlResult, Row};
+use crate::view::View;
+
+// Tables with more rows are not loaded at once but page by page while scrolling
+pub const LAZY_THRESHOLD: usize = 10_000;
+pub const PAGE_SIZE: usize = 500;
+pub const MAX_PAGES: usize = 16;
+
+// Least recently used cache of row pages. Pages are fetched by id (keyset pagination),
+// so the first id of every visited page is remembered to find the neighbouring ones.
+// Sorted views can't be paged by id and use offsets instead.
+#[derive(Debug, PartialEq, Clone)]
+pub struct PageCache {
+    row_count: usize,
+    page_size: usize,
+    max_pages: usize,
+    pages: HashMap<usize, Vec<Row>>,
+    // Page index -> lower bound of the ids in the page
+    page_starts: BTreeMap<usize, i32>,
+    // Least recently used page first
+    recent: VecDeque<usize>,
+    view: View,
+    // Received from a sender, there is no database to fetch the other pages from
+    received: bool,
+}
+
+impl PageCache {
+    pub fn new(row_count: usize, view: View) -> Self {
+        PageCache::with_page_size(row_count, PAGE_SIZE, MAX_PAGES, view)
+    }
+
+    pub fn with_page_size(
+        row_count: usize,
+        page_size: usize,
+        max_pages: usize,
+        view: View,
+    ) -> Self {
+        PageCache {
+            row_count,
+            page_size,
+            max_pages,
+            pages: HashMap::new(),
+            page_starts: BTreeMap::new(),
+            recent: VecDeque::new(),
+            view,
+            received: false,
+        }
+    }
+
+    // The rows a sender had in memory, by sheet position
+    pub fn received(row_count: usize, view: View, rows: Vec<(usize, Row)>) -> Self {
+        let mut cache = PageCache::new(row_count, view);
+        cache.received = true;
+        let page_size = cache.page_size;
+        for (index, row) in rows {
+            let page = cache.pages.entry(index / page_size).or_default();
+            // Pages are filled from their first row on
+            if page.len() == index % page_size {
+                page.push(row);
+            }
+        }
+        cache
+    }
+
+    pub fn row_count(&self) -> usize {
+        self.row_count
+    }
+
+    // Most consecutive rows that are in memory together, wherever they start
+    pub fn max_rows(&self) -> usize {
+        (self.max_pages - 1) * self.page_size
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Row> {
+        self.pages
+            .get(&(index / self.page_size))
+            .and_then(|page| page.get(index % self.page_size))
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut Row> {
+        let page_size = self.page_size;
+        self.pages
+            .get_mut(&(index / page_size))
+            .and_then(|page| page.get_mut(index % page_size))
+    }
+
+    pub fn find_mut(&mut self, row_id: i32) -> Option<&mut Row> {
+        self.pages
+            .values_mut()
+            .flat_map(|page| page.iter_mut())
+            .find(|row| row.id == row_id)
+    }
+
+    pub fn index_of(&self, row_id: i32) -> Option<usize> {
+        self.pages.iter().find_map(|(page, rows)| {
+            rows.iter()
+                .position(|row| row.id == row_id)
+                .map(|index| page * self.page_size + index)
+        })
+    }
+
+    // Cached rows with their sheet positions, in sheet order
+    pub fn rows(&self) -> Vec<(usize, &Row)> {
+        let mut pages: Vec<&usize> = self.pages.keys().collect();
+        pages.sort();
+        pages
+            .into_iter()
+            .flat_map(|page| {
+                let start = page * self.page_size;
+                self.pages[page]
+                    .iter()
+                    .enumerate()
+                    .map(move |(index, row)| (start + index, row))
+            })
+            .collect()
+    }
+
+    // Forgets everything, used when rows were inserted or deleted and positions shifted
+    pub fn reset(&mut self, row_count: usize) {
+        self.row_count = row_count;
+        self.pages.clear();
+        self.page_starts.clear();
+        self.recent.clear();
+    }
+
+    // Makes sure the page holding the row at `index` is cached
+    pub fn load(&mut self, db: &Database, columns: &[Column], index: usize) -> MySqlResult<()> {
+        if self.received || index >= self.row_count {
+            return Ok(());
+        }
+        let page = index / self.page_size;
+        if self.pages.contains_key(&page) {
+            self.touch(page);
+            return Ok(());
+        }
+
+        let rows = if self.view.sort.is_empty() {
+            let start = match self.page_start(db, columns, page)? {
+                Some(start) => start,
+                // Rows were deleted meanwhile
+                None => return Ok(()),
+            };
+            let rows = db.get_page(columns, &self.view, start, self.page_size)?;
+            if let Some(last) = rows.last() {
+                self.page_starts.insert(page + 1, last.id + 1);
+            }
+            rows
+        } else {
+            db.get_page_at(columns, &self.view, page * self.page_size, self.page_size)?
+        };
+        self.pages.insert(page, rows);
+        self.touch(page);
+
+        while self.recent.len() > self.max_pages {
+            if let Some(evicted) = self.recent.pop_front() {
+                self.pages.remove(&evicted);
+            }
+        }
+        Ok(())
+    }
+
+    fn page_start(
+        &mut self,
+        db: &Database,
+        columns: &[Column],
+        page: usize,
+    ) -> MySqlResult<Option<i32>> {
+        if let Some(start) = self.page_starts.get(&page) {
+            return Ok(Some(*start));
+        }
+        // Walk from the closest known page before this one, only ids are read on the way
+        let (known_page, known_start) = self
+            .page_starts
+            .range(..page)
+            .next_back()
+            .map(|(known_page, known_start)| (*known_page, *known_start))
+            .unwrap_or((0, i32::MIN));
+        let offset = (page - known_page) * self.page_size;
+        let start = db.nth_id_from(columns, &self.view, known_start, offset)?;
+        if let Some(start) = start {
+            self.page_starts.insert(page, start);
+        }
+        Ok(start)
+    }
+
+    fn touch(&mut self, page: usize) {
+        if self.recent.back() != Some(&page) {
+            self.recent.retain(|recent| *recent != page);
+            self.recent.push_back(page);
+        }
+    }
+}
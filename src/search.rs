@@ -0,0 +1,124 @@
+use regex::{NoExpand, Regex, RegexBuilder};
+
+use crate::error::Error;
+
+// Options of the find bar
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct SearchOptions {
+    pub case_sensitive: bool,
+    // The whole cell has to match, not just a part of it
+    pub whole_cell: bool,
+    // The text is a regular expression, replacements can refer to its groups as `$1`
+    pub regex: bool,
+}
+
+// What the find bar looks for, compiled to a regular expression either way
+#[derive(Debug, Clone)]
+pub struct Search {
+    pattern: Regex,
+    options: SearchOptions,
+}
+
+impl Search {
+    pub fn new(text: &str, options: SearchOptions) -> Result<Search, Error> {
+        if text.is_empty() {
+            return Err(Error::new("Nothing to find"));
+        }
+        let pattern = if options.regex {
+            text.to_owned()
+        } else {
+            regex::escape(text)
+        };
+        let pattern = if options.whole_cell {
+            format!("^(?:{})$", pattern)
+        } else {
+            pattern
+        };
+        let pattern = RegexBuilder::new(&pattern)
+            .case_insensitive(!options.case_sensitive)
+            .build()
+            .map_err(|error| Error::new(&format!("Invalid regular expression: {}", error)))?;
+        Ok(Search { pattern, options })
+    }
+
+    pub fn matches(&self, text: &str) -> bool {
+        self.pattern.is_match(text)
+    }
+
+    // The text with every match replaced
+    pub fn replace(&self, text: &str, replacement: &str) -> String {
+        if self.options.regex {
+            self.pattern.replace_all(text, replacement).into_owned()
+        } else {
+            self.pattern
+                .replace_all(text, NoExpand(replacement))
+                .into_owned()
+        }
+    }
+}
+
+// The match after (or before) the cell in reading order, wrapping around the sheet.
+// `found` is sorted by row, then column.
+pub fn next_match(
+    found: &[(usize, usize)],
+    from: (usize, usize),
+    forward: bool,
+) -> Option<(usize, usize)> {
+    if forward {
+        found
+            .iter()
+            .find(|cell| **cell > from)
+            .or_else(|| found.first())
+    } else {
+        found
+            .iter()
+            .rev()
+            .find(|cell| **cell < from)
+            .or_else(|| found.last())
+    }
+    .copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::search::{next_match, Search, SearchOptions};
+
+    #[test]
+    fn test_search() {
+        let plain = Search::new("a.b", SearchOptions::default()).unwrap();
+        assert!(plain.matches("xA.By"));
+        assert!(!plain.matches("axb"));
+        assert_eq!(plain.replace("a.b A.B", "$1"), "$1 $1");
+
+        let options = SearchOptions {
+            case_sensitive: true,
+            whole_cell: true,
+            regex: false,
+        };
+        let whole = Search::new("text", options).unwrap();
+        assert!(whole.matches("text"));
+        assert!(!whole.matches("Text"));
+        assert!(!whole.matches("more text"));
+
+        let options = SearchOptions {
+            regex: true,
+            ..Default::default()
+        };
+        let regex = Search::new(r"(\d+)-(\d+)", options).unwrap();
+        assert!(regex.matches("from 1-2"));
+        assert_eq!(regex.replace("1-2, 30-40", "$2-$1"), "2-1, 40-30");
+        assert!(Search::new("(open", options).is_err());
+        assert!(Search::new("", options).is_err());
+    }
+
+    #[test]
+    fn test_next_match() {
+        let found = [(0, 2), (1, 0), (3, 1)];
+        assert_eq!(next_match(&found, (0, 2), true), Some((1, 0)));
+        assert_eq!(next_match(&found, (2, 0), true), Some((3, 1)));
+        assert_eq!(next_match(&found, (3, 1), true), Some((0, 2)));
+        assert_eq!(next_match(&found, (1, 0), false), Some((0, 2)));
+        assert_eq!(next_match(&found, (0, 0), false), Some((3, 1)));
+        assert_eq!(next_match(&[], (0, 0), true), None);
+    }
+}
@@ -0,0 +1,1618 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+
+use mysql::serde_json::{json, Value as JsonValue};
+
+use crate::database::{
+    BatchError, CellUpdate, Change, Column, Database, MySqlResult, Row, UpdateError,
+};
+use crate::dependencies::DependencyGraph;
+use crate::draw_table::cell_name;
+use crate::error::Error;
+use crate::fill;
+use crate::formatting::{blend, parse_formats, Condition, FormatRule, Style, Target};
+use crate::formula::{
+    evaluate, is_formula, shift_references, to_boolean, CellError, CellRef, CellResult, Formula,
+};
+use crate::navigation::Selection;
+use crate::paging::{PageCache, LAZY_THRESHOLD, PAGE_SIZE};
+use crate::search::Search;
+use crate::validation::{parse_rules, Rule, Rules};
+use crate::value::{ColumnType, Value};
+use crate::view::{Operator, View};
+
+// A new value for the cell at the given sheet position. For formulas `value` is what it
+// computes to, converted to the column type.
+#[derive(Debug, PartialEq, Clone)]
+pub struct CellEdit {
+    pub row: usize,
+    pub col: usize,
+    pub value: Value,
+    pub formula: Option<String>,
+}
+
+// Rows of the sheet, either all of them or pages fetched on demand for big tables
+#[derive(Debug, PartialEq, Clone)]
+pub enum Rows {
+    Loaded(Vec<Row>),
+    Paged(PageCache),
+}
+
+impl Default for Rows {
+    fn default() -> Self {
+        Rows::Loaded(Vec::new())
+    }
+}
+
+// Formula references count rows in id order, the order without sorting, so sorting
+// doesn't change what they point to
+#[derive(Default, Debug, PartialEq, Clone)]
+pub struct RowOrder {
+    // Sheet positions of the rows in id order
+    by_id: Vec<usize>,
+    // Row number formulas use for every sheet position
+    references: Vec<usize>,
+}
+
+impl RowOrder {
+    fn new(ids: &[i32]) -> RowOrder {
+        let mut by_id: Vec<usize> = (0..ids.len()).collect();
+        by_id.sort_by_key(|row| ids[*row]);
+        let mut references = vec![0; ids.len()];
+        for (reference, row) in by_id.iter().enumerate() {
+            references[*row] = reference;
+        }
+        RowOrder { by_id, references }
+    }
+}
+
+// In-memory copy of the table shown in the GUI and sent to receivers
+#[derive(Default, Debug, PartialEq, Clone)]
+pub struct Sheet {
+    // Database the rows come from, receivers start over when it changes
+    pub dataset: String,
+    pub columns: Vec<Column>,
+    pub rows: Rows,
+    // Position in the database change log the rows are up to date with
+    pub last_change: u64,
+    // Sorting and filtering the rows are shown with
+    pub view: View,
+    // Formulas by row id and column name. References in them count rows in id order, so
+    // sorting doesn't change what they point to.
+    pub formulas: BTreeMap<(i32, String), Formula>,
+    // What the formulas refer to, as they refer to it
+    pub dependencies: DependencyGraph,
+    // The rows in memory in the order formulas count them
+    pub row_order: RowOrder,
+    // Validation rules by column name
+    pub rules: Rules,
+    // Edits a rule refused with the reason, by row id and column name, until the cell is
+    // edited again
+    pub rejected: BTreeMap<(i32, String), String>,
+    // Conditional formats in the order they apply, later ones win
+    pub formats: Vec<FormatRule>,
+    // Every row of the table for receivers of filtered views that are not shared, read once
+    // until the rows change
+    pub unfiltered_rows: RefCell<Option<Vec<Row>>>,
+}
+
+// Parse errors name the cell, the highlighted cell shows which one it is
+fn refusal(row: usize, col: usize, error: &Error) -> &str {
+    let prefix = format!("{}: ", cell_name(row, col));
+    error.details.trim_start_matches(&prefix)
+}
+
+// Parses (row id, column name, formula) triples, formulas that don't parse are dropped.
+// They are not computed yet.
+fn parse_formulas(formulas: Vec<(i32, String, String)>) -> BTreeMap<(i32, String), Formula> {
+    formulas
+        .into_iter()
+        .filter_map(|(row_id, column, source)| {
+            Some(((row_id, column), Formula::parse(&source).ok()?))
+        })
+        .collect()
+}
+
+// Numbers for color scales, text is left out
+fn number(value: &Value) -> Option<f64> {
+    value.as_f64().filter(|_| value.is_numeric())
+}
+
+// What a formula result is stored as in its column, NULL if it doesn't fit the type
+fn stored_value(column: &Column, result: &CellResult) -> Value {
+    match result {
+        Ok(value) => column.kind.parse(&value.to_string()).unwrap_or(Value::Null),
+        Err(_) => Value::Null,
+    }
+}
+
+impl Sheet {
+    pub fn n_rows(&self) -> usize {
+        match &self.rows {
+            Rows::Loaded(rows) => rows.len(),
+            Rows::Paged(cache) => cache.row_count(),
+        }
+    }
+
+    pub fn n_cols(&self) -> usize {
+        self.columns.len()
+    }
+
+    fn size(&self) -> (usize, usize) {
+        (self.n_rows(), self.n_cols())
+    }
+
+    // None if the row is out of range or its page is not loaded
+    pub fn row(&self, index: usize) -> Option<&Row> {
+        match &self.rows {
+            Rows::Loaded(rows) => rows.get(index),
+            Rows::Paged(cache) => cache.get(index),
+        }
+    }
+
+    pub fn row_mut(&mut self, index: usize) -> Option<&mut Row> {
+        match &mut self.rows {
+            Rows::Loaded(rows) => rows.get_mut(index),
+            Rows::Paged(cache) => cache.get_mut(index),
+        }
+    }
+
+    fn find_row_mut(&mut self, row_id: i32) -> Option<&mut Row> {
+        match &mut self.rows {
+            Rows::Loaded(rows) => rows.iter_mut().find(|row| row.id == row_id),
+            Rows::Paged(cache) => cache.find_mut(row_id),
+        }
+    }
+
+    // Sheet position of a row in memory
+    pub fn index_of(&self, row_id: i32) -> Option<usize> {
+        match &self.rows {
+            Rows::Loaded(rows) => rows.iter().position(|row| row.id == row_id),
+            Rows::Paged(cache) => cache.index_of(row_id),
+        }
+    }
+
+    // Rows currently in memory, in sheet order
+    pub fn loaded_rows(&self) -> Vec<&Row> {
+        match &self.rows {
+            Rows::Loaded(rows) => rows.iter().collect(),
+            Rows::Paged(cache) => cache.rows().into_iter().map(|(_, row)| row).collect(),
+        }
+    }
+
+    // Fetches the page holding the row if the sheet is paged
+    pub fn load_row(&mut self, db: &Database, index: usize) -> MySqlResult<()> {
+        match &mut self.rows {
+            Rows::Loaded(_) => Ok(()),
+            Rows::Paged(cache) if cache.get(index).is_some() => Ok(()),
+            Rows::Paged(cache) => {
+                cache.load(db, &self.columns, index)?;
+                // Formulas of the new rows and the ones referring to them. Received sheets
+                // have nothing to load.
+                if cache.get(index).is_some() {
+                    self.recalculate();
+                }
+                Ok(())
+            }
+        }
+    }
+
+    // The rows receivers get have to be read again
+    fn rows_changed(&mut self) {
+        *self.unfiltered_rows.get_mut() = None;
+    }
+
+    // Appends a row that was just inserted with the highest id
+    pub fn push_row(&mut self, row: Row) {
+        self.rows_changed();
+        match &mut self.rows {
+            Rows::Loaded(rows) => rows.push(row),
+            Rows::Paged(cache) => {
+                let row_count = cache.row_count() + 1;
+                cache.reset(row_count);
+            }
+        }
+        self.order_references();
+    }
+
+    // Puts the rows in memory in id order for formula references, after they moved, came
+    // or went
+    fn order_references(&mut self) {
+        let ids: Vec<i32> = match &self.rows {
+            Rows::Loaded(rows) => rows.iter().map(|row| row.id).collect(),
+            // Pages are either in id order already or sorted by the database
+            Rows::Paged(_) => Vec::new(),
+        };
+        self.row_order = RowOrder::new(&ids);
+    }
+
+    // Sheet position of the row formulas refer to as `row`, None if it's not in memory or
+    // the sheet is paged and sorted, which hides the id order
+    fn sheet_row(&self, row: usize) -> Option<usize> {
+        match &self.rows {
+            Rows::Loaded(_) => self.row_order.by_id.get(row).copied(),
+            Rows::Paged(_) if self.view.sort.is_empty() => Some(row),
+            Rows::Paged(_) => None,
+        }
+    }
+
+    // Row number formulas refer to the sheet row with
+    fn reference_row(&self, row: usize) -> Option<usize> {
+        match &self.rows {
+            Rows::Loaded(_) => self.row_order.references.get(row).copied(),
+            Rows::Paged(_) if self.view.sort.is_empty() => Some(row),
+            Rows::Paged(_) => None,
+        }
+    }
+
+    // How formulas refer to the cell at the sheet position
+    fn reference(&self, row: usize, col: usize) -> Option<CellRef> {
+        Some(CellRef {
+            row: self.reference_row(row)?,
+            col,
+        })
+    }
+
+    // Formula of the cell formulas refer to as `cell`
+    fn reference_formula(&self, cell: CellRef) -> Option<&Formula> {
+        self.formula(self.sheet_row(cell.row)?, cell.col)
+    }
+
+    pub fn value(&self, row: usize, col: usize) -> &Value {
+        self.row(row)
+            .and_then(|row| row.values.get(col))
+            .unwrap_or(&Value::Null)
+    }
+
+    pub fn set_value(&mut self, row: usize, col: usize, value: Value) {
+        if let Some(row) = self.row_mut(row) {
+            if row.values.len() <= col {
+                row.values.resize(col + 1, Value::Null);
+            }
+            row.values[col] = value;
+        }
+    }
+
+    // Row id and column name, formulas and refused edits stay with them when rows move
+    fn cell_key(&self, row: usize, col: usize) -> Option<(i32, String)> {
+        Some((self.row(row)?.id, self.columns.get(col)?.name.clone()))
+    }
+
+    pub fn formula(&self, row: usize, col: usize) -> Option<&Formula> {
+        self.formulas.get(&self.cell_key(row, col)?)
+    }
+
+    // What the cell shows: the result of its formula or its value
+    pub fn display_value(&self, row: usize, col: usize) -> Value {
+        match self.formula(row, col) {
+            Some(formula) => formula.display_value(),
+            None => self.value(row, col).clone(),
+        }
+    }
+
+    // What the editor starts with: the formula or the value
+    pub fn input_text(&self, row: usize, col: usize) -> String {
+        match self.formula(row, col) {
+            Some(formula) => formula.source.clone(),
+            None => self.value(row, col).to_string(),
+        }
+    }
+
+    // Paged sheets only keep some pages in memory, ranges with more rows can't be loaded
+    // at once
+    pub fn check_range_rows(&self, top: usize, bottom: usize) -> Result<(), Error> {
+        match &self.rows {
+            Rows::Paged(cache) if bottom + 1 - top > cache.max_rows() => Err(Error::new(&format!(
+                "Select at most {} rows of a big table",
+                cache.max_rows()
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    // What the cells between the corners show, row by row. Rows of big tables have to be
+    // loaded first.
+    pub fn range_text(
+        &self,
+        (top, left): (usize, usize),
+        (bottom, right): (usize, usize),
+    ) -> Vec<Vec<String>> {
+        (top..=bottom)
+            .map(|row| {
+                (left..=right)
+                    .map(|col| self.display_value(row, col).to_string())
+                    .collect()
+            })
+            .collect()
+    }
+
+    // Edits writing pasted text with its first cell at `(row, col)`. What falls outside
+    // the sheet is dropped.
+    pub fn paste_edits(
+        &mut self,
+        (row, col): (usize, usize),
+        text: &[Vec<String>],
+    ) -> Vec<CellEdit> {
+        let mut inputs = Vec::new();
+        for (row, line) in (row..self.n_rows()).zip(text) {
+            for (col, input) in (col..self.n_cols()).zip(line) {
+                inputs.push((row, col, input.clone()));
+            }
+        }
+        self.parse_inputs(inputs)
+    }
+
+    // Edits emptying the cells between the corners
+    pub fn clear_edits(
+        &mut self,
+        (top, left): (usize, usize),
+        (bottom, right): (usize, usize),
+    ) -> Vec<CellEdit> {
+        let empty = vec![vec![String::new(); right + 1 - left]; bottom + 1 - top];
+        self.paste_edits((top, left), &empty)
+    }
+
+    // Edits filling `target` from the `source` range it extends down, up, right or left.
+    // With `series` the values of every source row or column continue (1, 2 -> 3, 4),
+    // otherwise they repeat. Repeated formulas get their references moved along.
+    pub fn fill_edits(
+        &mut self,
+        source: Selection,
+        target: Selection,
+        series: bool,
+    ) -> Vec<CellEdit> {
+        let target = match target.clamp(self.n_rows(), self.n_cols()) {
+            Some(target) => target,
+            None => return Vec::new(),
+        };
+        let (top, left) = source.top_left();
+        let (bottom, right) = source.bottom_right();
+        let (target_top, target_left) = target.top_left();
+        let (target_bottom, target_right) = target.bottom_right();
+        // Source cells in fill order and the cells they continue to, per column or row
+        type Cells = Vec<(usize, usize)>;
+        let columns = |rows: Vec<usize>, filled: Vec<usize>| -> Vec<(Cells, Cells)> {
+            let cells = |rows: &[usize], col| rows.iter().map(|row| (*row, col)).collect();
+            (left..=right)
+                .map(|col| (cells(&rows, col), cells(&filled, col)))
+                .collect()
+        };
+        let rows = |cols: Vec<usize>, filled: Vec<usize>| -> Vec<(Cells, Cells)> {
+            let cells = |cols: &[usize], row| cols.iter().map(|col| (row, *col)).collect();
+            (top..=bottom)
+                .map(|row| (cells(&cols, row), cells(&filled, row)))
+                .collect()
+        };
+        let lines = if target_bottom > bottom {
+            columns(
+                (top..=bottom).collect(),
+                (bottom + 1..=target_bottom).collect(),
+            )
+        } else if target_top < top {
+            columns(
+                (top..=bottom).rev().collect(),
+                (target_top..top).rev().collect(),
+            )
+        } else if target_right > right {
+            rows(
+                (left..=right).collect(),
+                (right + 1..=target_right).collect(),
+            )
+        } else if target_left < left {
+            rows(
+                (left..=right).rev().collect(),
+                (target_left..left).rev().collect(),
+            )
+        } else {
+            Vec::new()
+        };
+
+        let mut filled_inputs = Vec::new();
+        let mut refused = Vec::new();
+        for (sources, filled) in lines {
+            let inputs: Vec<String> = sources
+                .iter()
+                .map(|(row, col)| self.input_text(*row, *col))
+                .collect();
+            let continued = if series && !inputs.iter().any(|input| is_formula(input)) {
+                fill::series(&inputs, filled.len())
+            } else {
+                None
+            };
+            for (index, (row, col)) in filled.into_iter().enumerate() {
+                let input = match &continued {
+                    Some(values) => values[index].clone(),
+                    None => {
+                        let (source_row, source_col) = sources[index % sources.len()];
+                        let input = &inputs[index % sources.len()];
+                        if is_formula(input) {
+                            // Formulas count rows in id order, not as sorted
+                            let counted = |row| self.reference_row(row).unwrap_or(row) as isize;
+                            let rows = counted(row) - counted(source_row);
+                            let cols = col as isize - source_col as isize;
+                            match shift_references(input, rows, cols) {
+                                Some(input) => input,
+                                None => {
+                                    refused.push((row, col));
+                                    continue;
+                                }
+                            }
+                        } else {
+                            input.clone()
+                        }
+                    }
+                };
+                filled_inputs.push((row, col, input));
+            }
+        }
+        for (row, col) in refused {
+            self.reject(row, col, "a reference would leave the sheet");
+        }
+        self.parse_inputs(filled_inputs)
+    }
+
+    // Cells whose input (formula or value) matches, row by row. Only loaded rows of big
+    // tables are searched.
+    pub fn find(&self, search: &Search) -> Vec<(usize, usize)> {
+        (0..self.n_rows())
+            .filter(|row| self.row(*row).is_some())
+            .flat_map(|row| (0..self.n_cols()).map(move |col| (row, col)))
+            .filter(|(row, col)| search.matches(&self.input_text(*row, *col)))
+            .collect()
+    }
+
+    pub fn rule(&self, col: usize) -> Option<&Rule> {
+        self.rules.get(&self.columns.get(col)?.name)
+    }
+
+    // Values the rule of the column allows, empty if it takes any
+    pub fn allowed_values(&self, col: usize) -> &[String] {
+        self.rule(col).map_or(&[], |rule| rule.allowed.as_slice())
+    }
+
+    pub fn check_value(&self, col: usize, value: &Value) -> Result<(), Error> {
+        match self.rule(col) {
+            Some(rule) => rule.check(value),
+            None => Ok(()),
+        }
+    }
+
+    // Edits that pass the column rules. The others are remembered, their cells are
+    // highlighted until edited again.
+    pub fn validate_edits(&mut self, edits: &[CellEdit]) -> Vec<CellEdit> {
+        let mut valid = Vec::new();
+        for edit in edits {
+            match self.check_value(edit.col, &edit.value) {
+                Ok(()) => {
+                    self.clear_rejected(edit.row, edit.col);
+                    valid.push(edit.clone());
+                }
+                Err(error) => self.reject(edit.row, edit.col, &error.details),
+            }
+        }
+        valid
+    }
+
+    pub fn reject(&mut self, row: usize, col: usize, reason: &str) {
+        if let Some(key) = self.cell_key(row, col) {
+            self.rejected.insert(key, reason.to_owned());
+        }
+    }
+
+    // Remembers why the input of the cell was refused
+    pub fn reject_input(&mut self, row: usize, col: usize, error: &Error) {
+        self.reject(row, col, refusal(row, col, error));
+    }
+
+    // Edits of the (row, column, input) cells. Inputs that don't parse are left out, their
+    // cells highlighted like edits breaking a rule.
+    fn parse_inputs(&mut self, inputs: Vec<(usize, usize, String)>) -> Vec<CellEdit> {
+        let mut edits = Vec::new();
+        for (row, col, input) in inputs {
+            match self.parse_edit(row, col, &input) {
+                Ok(edit) => edits.push(edit),
+                Err(error) => self.reject_input(row, col, &error),
+            }
+        }
+        edits
+    }
+
+    pub fn clear_rejected(&mut self, row: usize, col: usize) {
+        if let Some(key) = self.cell_key(row, col) {
+            self.rejected.remove(&key);
+        }
+    }
+
+    // Why the cell is highlighted: its last edit was refused or its value breaks the rule,
+    // e.g. written before the rule was
+    pub fn cell_error(&self, row: usize, col: usize) -> Option<String> {
+        let key = self.cell_key(row, col)?;
+        if let Some(reason) = self.rejected.get(&key) {
+            return Some(reason.clone());
+        }
+        let value = self.row(row)?.values.get(col)?;
+        self.check_value(col, value)
+            .err()
+            .map(|error| error.details)
+    }
+
+    // Cells of the target in memory
+    fn target_cells(&self, target: &Target) -> Vec<(usize, usize)> {
+        (0..self.n_rows())
+            .filter(|row| self.row(*row).is_some())
+            .flat_map(|row| (0..self.n_cols()).map(move |col| (row, col)))
+            .filter(|(row, col)| {
+                let column = &self.columns[*col].name;
+                target.offset(*row, *col, column).is_some()
+            })
+            .collect()
+    }
+
+    // Smallest and largest number in the target of every color scale, by format. Computed
+    // once per redraw, big tables only count the rows in memory.
+    pub fn scale_bounds(&self) -> Vec<Option<(f64, f64)>> {
+        self.formats
+            .iter()
+            .map(|format| {
+                if !matches!(format.condition, Condition::Scale(..)) {
+                    return None;
+                }
+                self.target_cells(&format.target)
+                    .into_iter()
+                    .filter_map(|(row, col)| number(&self.display_value(row, col)))
+                    .fold(None, |bounds, number| match bounds {
+                        Some((min, max)) => Some((number.min(min), number.max(max))),
+                        None => Some((number, number)),
+                    })
+            })
+            .collect()
+    }
+
+    // Colors and font of the cell from the formats that apply to it. `bounds` are from
+    // `scale_bounds`.
+    pub fn cell_style(&self, row: usize, col: usize, bounds: &[Option<(f64, f64)>]) -> Style {
+        let mut style = Style::default();
+        let column = match self.columns.get(col) {
+            Some(column) => column,
+            None => return style,
+        };
+        let value = self.display_value(row, col);
+        for (index, format) in self.formats.iter().enumerate() {
+            let (drow, dcol) = match format.target.offset(row, col, &column.name) {
+                Some(offset) => offset,
+                None => continue,
+            };
+            match &format.condition {
+                Condition::Compare(operator, operand) => {
+                    let operand = match operator {
+                        Operator::Contains | Operator::NotContains => Value::Text(operand.clone()),
+                        _ if operand.eq_ignore_ascii_case("null") => Value::Null,
+                        _ => column
+                            .kind
+                            .parse(operand)
+                            .unwrap_or_else(|_| Value::Text(operand.clone())),
+                    };
+                    if operator.matches(&value, &operand) {
+                        style.merge(&format.style);
+                    }
+                }
+                Condition::Formula(formula) => {
+                    // References move along with the cell, in the rows formulas count
+                    let reference_row = match self.reference_row(row) {
+                        Some(reference_row) => reference_row,
+                        None => continue,
+                    };
+                    let result = evaluate(&formula.expr, self.size(), &mut |cell| {
+                        let moved = (cell.row + drow + reference_row).checked_sub(row);
+                        match moved {
+                            Some(moved) => self.cell_result(CellRef {
+                                row: moved,
+                                col: cell.col + dcol,
+                            }),
+                            None => Ok(Value::Null),
+                        }
+                    });
+                    if result.and_then(|result| to_boolean(&result)) == Ok(true) {
+                        style.merge(&format.style);
+                    }
+                }
+                Condition::Scale(low, high) => {
+                    let bounds = bounds.get(index).copied().flatten();
+                    if let (Some((min, max)), Some(number)) = (bounds, number(&value)) {
+                        let share = if max > min {
+                            (number - min) / (max - min)
+                        } else {
+                            0.5
+                        };
+                        style.merge(&Style {
+                            background: Some(blend(*low, *high, share)),
+                            ..format.style
+                        });
+                    }
+                }
+            }
+        }
+        style
+    }
+
+    // Edits replacing the matches in the given cells
+    pub fn replace_edits(
+        &mut self,
+        search: &Search,
+        replacement: &str,
+        cells: &[(usize, usize)],
+    ) -> Vec<CellEdit> {
+        let mut inputs = Vec::new();
+        for (row, col) in cells.iter().copied() {
+            let input = self.input_text(row, col);
+            let replaced = search.replace(&input, replacement);
+            if replaced != input {
+                inputs.push((row, col, replaced));
+            }
+        }
+        self.parse_inputs(inputs)
+    }
+
+    // Replace all of big tables, whose rows are not all in memory: every row of the view is
+    // read from the database page by page. Returns the updates with the rows they change.
+    // Cells whose new value breaks a rule are left out and highlighted.
+    pub fn replace_updates(
+        &mut self,
+        db: &Database,
+        search: &Search,
+        replacement: &str,
+    ) -> Result<Vec<(Row, CellUpdate)>, Error> {
+        let mut updates = Vec::new();
+        let mut start = i32::MIN;
+        // Position in id order, the sheet position unless the view is sorted
+        let mut position = 0;
+        loop {
+            let rows = db.get_page(&self.columns, &self.view, start, PAGE_SIZE)?;
+            start = match rows.last() {
+                Some(last) => last.id + 1,
+                None => break,
+            };
+            for row in rows {
+                for col in 0..self.n_cols() {
+                    let column = self.columns[col].clone();
+                    let key = (row.id, column.name.clone());
+                    let input = match self.formulas.get(&key) {
+                        Some(formula) => formula.source.clone(),
+                        None => row.values.get(col).unwrap_or(&Value::Null).to_string(),
+                    };
+                    if !search.matches(&input) {
+                        continue;
+                    }
+                    let replaced = search.replace(&input, replacement);
+                    if replaced == input {
+                        continue;
+                    }
+                    let checked = self
+                        .parse_edit(position, col, &replaced)
+                        .and_then(|edit| self.check_value(col, &edit.value).map(|()| edit));
+                    let edit = match checked {
+                        Ok(edit) => edit,
+                        Err(error) => {
+                            let reason = refusal(position, col, &error);
+                            self.rejected.insert(key, reason.to_owned());
+                            continue;
+                        }
+                    };
+                    self.rejected.remove(&key);
+                    let update = CellUpdate {
+                        row_id: row.id,
+                        version: row.version,
+                        column,
+                        value: edit.value,
+                        formula: edit.formula,
+                    };
+                    updates.push((row.clone(), update));
+                }
+                position += 1;
+            }
+        }
+        Ok(updates)
+    }
+
+    // Writes updates of rows that don't have to be in memory in one transaction, then
+    // brings the rows in memory and the formulas up to date
+    pub fn apply_updates(
+        &mut self,
+        db: &Database,
+        updates: &[CellUpdate],
+    ) -> Result<(), BatchError> {
+        db.update_or_queue(&self.columns, updates)?;
+        self.rows_changed();
+
+        for update in updates {
+            let col = self
+                .columns
+                .iter()
+                .position(|column| *column == update.column);
+            if let (Some(row), Some(col)) = (self.index_of(update.row_id), col) {
+                self.set_value(row, col, update.value.clone());
+                if let Some(row) = self.row_mut(row) {
+                    row.version += 1;
+                }
+            }
+            let key = (update.row_id, update.column.name.clone());
+            match update.formula.as_deref().map(Formula::parse) {
+                Some(Ok(formula)) => self.formulas.insert(key, formula),
+                _ => self.formulas.remove(&key),
+            };
+        }
+        self.recalculate();
+        Ok(())
+    }
+
+    // Replaces the formulas with the given (row id, column name, formula) ones and computes
+    // them. Formulas that don't parse anymore are dropped.
+    pub fn set_formulas(&mut self, formulas: Vec<(i32, String, String)>) {
+        self.formulas = parse_formulas(formulas);
+        self.recalculate();
+    }
+
+    // Result of a cell for formulas referring to it, rows that are not in memory are empty
+    fn cell_result(&self, cell: CellRef) -> CellResult {
+        if cell.col >= self.n_cols() {
+            return Err(CellError::Ref);
+        }
+        let row = match self.sheet_row(cell.row) {
+            Some(row) => row,
+            None => return Ok(Value::Null),
+        };
+        match self.formula(row, cell.col) {
+            Some(formula) => formula.result.clone(),
+            None => Ok(self.value(row, cell.col).clone()),
+        }
+    }
+
+    // Puts what the cell refers to into the dependency graph, or takes it out if the cell
+    // holds no formula
+    fn update_dependencies(&mut self, cell: CellRef) {
+        match self.reference_formula(cell) {
+            Some(formula) => {
+                let references = formula.expr.references();
+                self.dependencies.set(cell, references);
+            }
+            None => self.dependencies.remove(cell),
+        }
+    }
+
+    // Computes the formulas of the rows in memory and rebuilds the dependency graph, e.g.
+    // after the rows moved
+    pub fn recalculate(&mut self) {
+        self.order_references();
+        let columns: HashMap<&str, usize> = self
+            .columns
+            .iter()
+            .enumerate()
+            .map(|(col, column)| (column.name.as_str(), col))
+            .collect();
+        let cells: Vec<CellRef> = self
+            .formulas
+            .keys()
+            .filter_map(|(row_id, column)| {
+                self.reference(self.index_of(*row_id)?, *columns.get(column.as_str())?)
+            })
+            .collect();
+        self.dependencies.clear();
+        for cell in cells.iter() {
+            self.update_dependencies(*cell);
+        }
+        self.recalculate_cells(&cells);
+    }
+
+    // Computes again the formulas depending on the changed cells, in an order where every
+    // formula comes after the ones it refers to. Formulas in a cycle get `#CYCLE!`.
+    pub fn recalculate_cells(&mut self, changed: &[CellRef]) {
+        let (order, cyclic) = self
+            .dependencies
+            .order(&self.dependencies.affected(changed));
+        let results = cyclic.into_iter().map(|cell| (cell, Err(CellError::Cycle)));
+        for (cell, result) in results {
+            self.set_result(cell, result);
+        }
+        for cell in order {
+            let result = match self.reference_formula(cell) {
+                Some(formula) => evaluate(&formula.expr, self.size(), &mut |reference| {
+                    self.cell_result(reference)
+                }),
+                None => continue,
+            };
+            self.set_result(cell, result);
+        }
+    }
+
+    fn set_result(&mut self, cell: CellRef, result: CellResult) {
+        let key = self
+            .sheet_row(cell.row)
+            .and_then(|row| self.cell_key(row, cell.col));
+        if let Some(key) = key {
+            if let Some(formula) = self.formulas.get_mut(&key) {
+                formula.result = result;
+            }
+        }
+    }
+
+    // Parses user input for a cell according to the column type, or as a formula if it
+    // starts with `=`
+    pub fn parse_edit(&self, row: usize, col: usize, input: &str) -> Result<CellEdit, Error> {
+        let column = self
+            .columns
+            .get(col)
+            .ok_or_else(|| Error::new("No such column"))?;
+        let cell_error = |error: Error| {
+            Error::new(format!("{}: {}", cell_name(row, col), error.details).as_str())
+        };
+        if is_formula(input) {
+            let formula = Formula::parse(input).map_err(cell_error)?;
+            // Referring to the cell itself or to a formula depending on it is circular
+            let cell = self.reference(row, col);
+            let dependents = cell
+                .map(|cell| self.dependencies.affected(&[cell]))
+                .unwrap_or_default();
+            let references = formula.expr.references();
+            let result = if references.iter().any(|reference| {
+                cell.is_some_and(|cell| reference.contains(cell))
+                    || dependents
+                        .iter()
+                        .any(|dependent| reference.contains(*dependent))
+            }) {
+                Err(CellError::Cycle)
+            } else {
+                evaluate(&formula.expr, self.size(), &mut |reference| {
+                    self.cell_result(reference)
+                })
+            };
+            return Ok(CellEdit {
+                row,
+                col,
+                value: stored_value(column, &result),
+                formula: Some(formula.source),
+            });
+        }
+        let value = column.kind.parse(input).map_err(cell_error)?;
+        Ok(CellEdit {
+            row,
+            col,
+            value,
+            formula: None,
+        })
+    }
+
+    // Writes all edits in one transaction. The sheet is only changed after the commit
+    // (or after queueing while offline), so a failed batch leaves both the database
+    // and the sheet as they were.
+    pub fn apply_edits(&mut self, db: &Database, edits: &[CellEdit]) -> Result<(), BatchError> {
+        let mut updates: Vec<CellUpdate> = Vec::with_capacity(edits.len());
+        for (index, edit) in edits.iter().enumerate() {
+            self.load_row(db, edit.row)?;
+            let row = self.row(edit.row).ok_or(BatchError {
+                update: Some(index),
+                error: UpdateError::Conflict(None),
+            })?;
+            updates.push(CellUpdate {
+                row_id: row.id,
+                version: row.version,
+                column: self.columns[edit.col].clone(),
+                value: edit.value.clone(),
+                formula: edit.formula.clone(),
+            });
+        }
+
+        // Offline edits are kept in the sheet and written once the database is back
+        db.update_or_queue(&self.columns, &updates)?;
+        self.rows_changed();
+
+        let mut cells = Vec::with_capacity(edits.len());
+        for edit in edits {
+            self.set_value(edit.row, edit.col, edit.value.clone());
+            if let Some(key) = self.cell_key(edit.row, edit.col) {
+                match edit.formula.as_deref().map(Formula::parse) {
+                    Some(Ok(formula)) => self.formulas.insert(key, formula),
+                    _ => self.formulas.remove(&key),
+                };
+            }
+            if let Some(row) = self.row_mut(edit.row) {
+                row.version += 1;
+            }
+            if let Some(cell) = self.reference(edit.row, edit.col) {
+                self.update_dependencies(cell);
+                cells.push(cell);
+            }
+        }
+        // Only what depends on the edited cells
+        self.recalculate_cells(&cells);
+        Ok(())
+    }
+
+    // Replaces the row with the same id, e.g. after another client changed it
+    pub fn replace_row(&mut self, row: Row) {
+        self.rows_changed();
+        if let Some(existing) = self.find_row_mut(row.id) {
+            *existing = row;
+        }
+        self.recalculate();
+    }
+
+    // Pulls in rows changed in the database since the sheet was loaded, returns true if any
+    pub fn refresh(&mut self, db: &Database) -> Result<bool, Error> {
+        let (last_change, changes) = db.changes_since(&self.columns, self.last_change)?;
+        self.last_change = last_change;
+        if changes.is_empty() {
+            return Ok(false);
+        }
+        self.rows_changed();
+        // Formulas are written together with the rows, so they only change with them.
+        // They are computed once the rows are in place.
+        let formulas = db.get_formulas()?;
+
+        // Changed rows may move or start and stop matching the filters
+        if !self.view.is_empty() {
+            match &mut self.rows {
+                Rows::Loaded(rows) => *rows = db.get_rows(&self.columns, &self.view)?,
+                Rows::Paged(cache) => cache.reset(db.count_rows(&self.columns, &self.view)?),
+            }
+            self.set_formulas(formulas);
+            return Ok(true);
+        }
+
+        if let Rows::Paged(cache) = &mut self.rows {
+            // Only cached rows can be updated in place, anything else may shift positions
+            let in_place = changes.iter().all(|change| match change {
+                Change::Upsert(row) => cache.find_mut(row.id).is_some(),
+                Change::Delete(_) => false,
+            });
+            if !in_place {
+                cache.reset(db.count_rows(&self.columns, &self.view)?);
+                self.set_formulas(formulas);
+                return Ok(true);
+            }
+        }
+
+        let mut changed = false;
+        for change in changes {
+            changed |= self.apply_change(change);
+        }
+        self.set_formulas(formulas);
+        Ok(changed)
+    }
+
+    fn apply_change(&mut self, change: Change) -> bool {
+        match change {
+            Change::Upsert(row) => match self.find_row_mut(row.id) {
+                Some(existing) if *existing == row => false,
+                Some(existing) => {
+                    *existing = row;
+                    true
+                }
+                None => {
+                    self.push_row(row);
+                    true
+                }
+            },
+            Change::Delete(row_id) => match &mut self.rows {
+                Rows::Loaded(rows) => {
+                    let n_rows = rows.len();
+                    rows.retain(|row| row.id != row_id);
+                    rows.len() != n_rows
+                }
+                Rows::Paged(_) => unreachable!("Paged sheets reset on deletes"),
+            },
+        }
+    }
+
+    fn order_rows(&mut self, view: View) {
+        if let Rows::Loaded(rows) = &mut self.rows {
+            view.apply(&self.columns, rows);
+        }
+        self.view = view;
+    }
+
+    // Sorts and filters rows in memory, used when there is no database behind the sheet
+    pub fn apply_view(&mut self, view: View) {
+        self.order_rows(view);
+        // Filtered rows are gone, what refers to them changes
+        self.recalculate();
+    }
+
+    // Cells whose value or formula differ from the other sheet, None if it doesn't have
+    // the same columns and rows in the same places
+    fn changed_cells(&self, other: &Sheet) -> Option<Vec<(usize, usize)>> {
+        if self.dataset != other.dataset || self.columns != other.columns {
+            return None;
+        }
+        let (rows, other_rows) = match (&self.rows, &other.rows) {
+            (Rows::Loaded(rows), Rows::Loaded(other_rows)) if rows.len() == other_rows.len() => {
+                (rows, other_rows)
+            }
+            _ => return None,
+        };
+        let mut cells = Vec::new();
+        for (row, (current, other_row)) in rows.iter().zip(other_rows.iter()).enumerate() {
+            if current.id != other_row.id {
+                return None;
+            }
+            for col in 0..self.n_cols() {
+                let source = |sheet: &Sheet| {
+                    sheet
+                        .formula(row, col)
+                        .map(|formula| formula.source.clone())
+                };
+                if current.values.get(col) != other_row.values.get(col)
+                    || source(self) != source(other)
+                {
+                    cells.push((row, col));
+                }
+            }
+        }
+        Some(cells)
+    }
+
+    // Takes over a sheet received from the sender, shown with `view` unless the sender
+    // shares its own. If the rows stayed in place only the formulas depending on changed
+    // cells are computed again.
+    pub fn receive(&mut self, mut incoming: Sheet, view: &View) {
+        if !incoming.view.shared {
+            incoming.order_rows(view.clone());
+        }
+        let changed = self.changed_cells(&incoming);
+        let previous = std::mem::replace(self, incoming);
+        let cells = match changed {
+            Some(cells) => cells,
+            None => return self.recalculate(),
+        };
+        // The rows stayed in place, so did what formulas refer to
+        self.order_references();
+        let cells: Vec<CellRef> = cells
+            .into_iter()
+            .filter_map(|(row, col)| self.reference(row, col))
+            .collect();
+        for (key, formula) in self.formulas.iter_mut() {
+            if let Some(old) = previous.formulas.get(key) {
+                if old.source == formula.source {
+                    formula.result = old.result.clone();
+                }
+            }
+        }
+        self.dependencies = previous.dependencies;
+        for cell in cells.iter() {
+            self.update_dependencies(*cell);
+        }
+        self.recalculate_cells(&cells);
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        // Paged sheets only send the rows in memory, with their positions
+        match &self.rows {
+            Rows::Loaded(rows) => self.rows_json(rows.iter().enumerate().collect(), false),
+            Rows::Paged(cache) => self.rows_json(cache.rows(), true),
+        }
+    }
+
+    // The sheet with the given rows and their positions
+    fn rows_json(&self, rows: Vec<(usize, &Row)>, paged: bool) -> JsonValue {
+        let rows: Vec<JsonValue> = rows
+            .into_iter()
+            .map(|(index, row)| {
+                let mut json = json!({
+                    "id": row.id,
+                    "version": row.version,
+                    "values": row.values.iter().map(Value::to_json).collect::<Vec<_>>(),
+                });
+                if paged {
+                    json["index"] = json!(index);
+                }
+                json
+            })
+            .collect();
+        let mut json = json!({
+            "dataset": self.dataset,
+            "columns": self.columns.iter().map(|column| json!({
+                "name": column.name,
+                "type": column.kind.name(),
+            })).collect::<Vec<_>>(),
+            "rows": rows,
+            "formulas": self.formulas.iter().map(|((row_id, column), formula)| json!({
+                "row": row_id,
+                "column": column,
+                "formula": formula.source,
+            })).collect::<Vec<_>>(),
+            "rules": self.rules.iter().map(|(column, rule)| json!({
+                "column": column,
+                "rule": rule.to_string(),
+            })).collect::<Vec<_>>(),
+            "formats": self.formats.iter().map(|format| json!({
+                "target": format.target.to_string(),
+                "format": format.to_string(),
+            })).collect::<Vec<_>>(),
+        });
+        if self.view.shared {
+            json["view"] = self.view.to_json();
+        }
+        if paged {
+            json["row_count"] = json!(self.n_rows());
+        }
+        json
+    }
+
+    // What receivers get. If the view is not shared they sort and filter the rows their own
+    // way, so they get every row instead of the filtered ones, unless there are too many
+    // or the database is offline. Big tables send the pages in memory either way.
+    pub fn receiver_json(&self, db: &Database) -> JsonValue {
+        let filtered = !self.view.shared && !self.view.filters.is_empty();
+        if let (Rows::Loaded(_), true) = (&self.rows, filtered) {
+            let mut unfiltered_rows = self.unfiltered_rows.borrow_mut();
+            if unfiltered_rows.is_none() {
+                let unfiltered = View::default();
+                *unfiltered_rows = db
+                    .count_rows(&self.columns, &unfiltered)
+                    .ok()
+                    .filter(|count| *count <= LAZY_THRESHOLD)
+                    .and_then(|_| db.get_rows(&self.columns, &unfiltered).ok());
+            }
+            // The view isn't shared, so it is left out either way
+            if let Some(rows) = unfiltered_rows.as_ref() {
+                return self.rows_json(rows.iter().enumerate().collect(), false);
+            }
+        }
+        self.to_json()
+    }
+
+    pub fn from_json(json: &JsonValue) -> Result<Sheet, Error> {
+        let invalid = || Error::new("Invalid sheet payload");
+
+        let columns = json["columns"]
+            .as_array()
+            .ok_or_else(invalid)?
+            .iter()
+            .map(|column| {
+                let name = column["name"].as_str().ok_or_else(invalid)?;
+                let kind = column["type"]
+                    .as_str()
+                    .and_then(ColumnType::from_name)
+                    .ok_or_else(invalid)?;
+                Ok(Column::new(name, kind))
+            })
+            .collect::<Result<Vec<Column>, Error>>()?;
+
+        let view = if json["view"].is_null() {
+            View::default()
+        } else {
+            View::from_json(&json["view"], &columns)
+        };
+
+        // With their positions if the sender's sheet is paged
+        let rows = json["rows"]
+            .as_array()
+            .ok_or_else(invalid)?
+            .iter()
+            .map(|row| {
+                let index = row["index"].as_u64().map(|index| index as usize);
+                let id = row["id"].as_i64().ok_or_else(invalid)? as i32;
+                let version = row["version"].as_i64().unwrap_or_default() as i32;
+                let values = row["values"]
+                    .as_array()
+                    .ok_or_else(invalid)?
+                    .iter()
+                    .zip(columns.iter())
+                    .map(|(value, column)| column.kind.decode_json(value))
+                    .collect::<Result<Vec<Value>, Error>>()?;
+                let row = Row {
+                    id,
+                    version,
+                    values,
+                };
+                Ok((index, row))
+            })
+            .collect::<Result<Vec<(Option<usize>, Row)>, Error>>()?;
+        let rows = match json["row_count"].as_u64() {
+            Some(row_count) => {
+                let rows = rows
+                    .into_iter()
+                    .map(|(index, row)| Some((index?, row)))
+                    .collect::<Option<Vec<(usize, Row)>>>()
+                    .ok_or_else(invalid)?;
+                Rows::Paged(PageCache::received(row_count as usize, view.clone(), rows))
+            }
+            None => Rows::Loaded(rows.into_iter().map(|(_, row)| row).collect()),
+        };
+
+        // Missing in payloads of older senders
+        let formulas = json["formulas"]
+            .as_array()
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+            .iter()
+            .map(|formula| {
+                let row_id = formula["row"].as_i64().ok_or_else(invalid)? as i32;
+                let column = formula["column"].as_str().ok_or_else(invalid)?;
+                let source = formula["formula"].as_str().ok_or_else(invalid)?;
+                Ok((row_id, column.to_owned(), source.to_owned()))
+            })
+            .collect::<Result<Vec<(i32, String, String)>, Error>>()?;
+
+        let rules = json["rules"]
+            .as_array()
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+            .iter()
+            .map(|rule| {
+                let column = rule["column"].as_str().ok_or_else(invalid)?;
+                let rule = rule["rule"].as_str().ok_or_else(invalid)?;
+                Ok((column.to_owned(), rule.to_owned()))
+            })
+            .collect::<Result<Vec<(String, String)>, Error>>()?;
+
+        let formats = json["formats"]
+            .as_array()
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+            .iter()
+            .map(|format| {
+                let target = format["target"].as_str().ok_or_else(invalid)?;
+                let format = format["format"].as_str().ok_or_else(invalid)?;
+                Ok((target.to_owned(), format.to_owned()))
+            })
+            .collect::<Result<Vec<(String, String)>, Error>>()?;
+
+        // Computed by `receive`, which knows the view and what changed
+        Ok(Sheet {
+            dataset: json["dataset"].as_str().unwrap_or_default().to_owned(),
+            columns,
+            rows,
+            view,
+            formulas: parse_formulas(formulas),
+            rules: parse_rules(rules),
+            formats: parse_formats(formats),
+            ..Default::default()
+        })
+    }
+}
+
+// Names the cell that made the batch fail
+pub fn describe_error(edits: &[CellEdit], error: &BatchError) -> String {
+    match error.update.and_then(|index| edits.get(index)) {
+        Some(edit) => format!("{}: {}", cell_name(edit.row, edit.col), error),
+        None => error.to_string(),
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use crate::database::{example_columns, example_rows, Row};
+    use crate::formatting::{parse_formats, Style};
+    use crate::formula::Formula;
+    use crate::navigation::Selection;
+    use crate::paging::PageCache;
+    use crate::search::{Search, SearchOptions};
+    use crate::sheet::{CellEdit, Rows, Sheet};
+    use crate::validation::Rule;
+    use crate::value::Value;
+    use crate::view::View;
+
+    // A sheet of the example columns
+    pub(crate) fn sheet(rows: Rows) -> Sheet {
+        Sheet {
+            columns: example_columns(),
+            rows,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_sheet_formulas() {
+        let mut sheet = sheet(Rows::Loaded(example_rows()));
+        let formula = |row_id: i32, column: &str, source: &str| {
+            (row_id, column.to_owned(), source.to_owned())
+        };
+        sheet.set_formulas(vec![
+            formula(1, "ratio", "=A1 * 2"),
+            formula(2, "ratio", "=D1 + SUM(A1:A3)"),
+            formula(3, "text", "=B3 & \"!\""),
+            formula(3, "price", "=not a formula"),
+        ]);
+        assert_eq!(sheet.formulas.len(), 3);
+        assert_eq!(sheet.display_value(0, 3), Value::Integer(2));
+        assert_eq!(sheet.display_value(1, 3), Value::Integer(-3131));
+        assert_eq!(sheet.display_value(2, 1), Value::Text("#CYCLE!".to_owned()));
+        assert_eq!(sheet.input_text(0, 3), "=A1 * 2");
+        assert_eq!(sheet.input_text(0, 0), "1");
+
+        // References count rows in id order, sorting doesn't change what they point to
+        let mut view = View::default();
+        view.toggle_sort("number", false);
+        sheet.apply_view(view);
+        assert_eq!(sheet.row(0).unwrap().id, 3);
+        assert_eq!(sheet.display_value(1, 3), Value::Integer(2));
+        assert_eq!(sheet.display_value(2, 3), Value::Integer(-3131));
+        assert_eq!(sheet.display_value(0, 1), Value::Text("#CYCLE!".to_owned()));
+
+        // The column gets the result converted to its type. The first row is A3 now.
+        let edit = sheet.parse_edit(0, 0, "=A2 / 2").unwrap();
+        assert_eq!(edit.value, Value::Integer(50));
+        assert_eq!(edit.formula.as_deref(), Some("=A2 / 2"));
+        assert_eq!(
+            sheet.parse_edit(0, 0, "=A2 / 3").unwrap().value,
+            Value::Null
+        );
+        assert_eq!(
+            sheet.parse_edit(0, 0, "=A3 + 1").unwrap().value,
+            Value::Null
+        );
+        assert!(sheet.parse_edit(0, 0, "=A1 +").is_err());
+        assert_eq!(sheet.parse_edit(0, 0, "7").unwrap().formula, None);
+        // D2 refers to A1:A3
+        assert_eq!(sheet.parse_edit(0, 0, "=D2").unwrap().value, Value::Null);
+    }
+
+    #[test]
+    fn test_sheet_clipboard() {
+        let mut sheet = sheet(Rows::Loaded(example_rows()));
+        sheet.set_formulas(vec![(2, "ratio".to_owned(), "=A1 + A2".to_owned())]);
+        assert_eq!(
+            sheet.range_text((0, 2), (1, 3)),
+            vec![vec!["9.99", "0.5"], vec!["-120.50", "101"]]
+        );
+
+        let text = |rows: &[&[&str]]| -> Vec<Vec<String>> {
+            rows.iter()
+                .map(|row| row.iter().map(|cell| cell.to_string()).collect())
+                .collect()
+        };
+        let edits = sheet.paste_edits(
+            (1, 5),
+            &text(&[&["2021-01-02", "", "dropped"], &["", "=A1"]]),
+        );
+        let cells: Vec<(usize, usize)> = edits.iter().map(|edit| (edit.row, edit.col)).collect();
+        assert_eq!(cells, vec![(1, 5), (1, 6), (2, 5), (2, 6)]);
+        assert_eq!(edits[1].value, Value::Null);
+        assert_eq!(edits[3].formula.as_deref(), Some("=A1"));
+        // Text in a number column is left out and highlighted
+        let edits = sheet.paste_edits((0, 0), &text(&[&["1", "a"], &["x"]]));
+        assert_eq!(edits.len(), 2);
+        assert!(sheet.cell_error(1, 0).is_some());
+
+        let edits = sheet.clear_edits((1, 0), (2, 1));
+        assert_eq!(edits.len(), 4);
+        assert!(edits.iter().all(|edit| edit.value == Value::Null));
+    }
+
+    #[test]
+    fn test_sheet_fill() {
+        let mut sheet = sheet(Rows::Loaded(example_rows()));
+        sheet.set_formulas(vec![(1, "ratio".to_owned(), "=A1 * 2".to_owned())]);
+        let inputs = |edits: Vec<CellEdit>| -> Vec<(usize, usize, String)> {
+            edits
+                .into_iter()
+                .map(|edit| {
+                    let input = match edit.formula {
+                        Some(formula) => formula,
+                        None => edit.value.to_string(),
+                    };
+                    (edit.row, edit.col, input)
+                })
+                .collect()
+        };
+
+        // A1:A2 hold 1 and 100, the series continues with the same step
+        let source = Selection::cell(0, 0).extend((1, 0));
+        let target = source.extend((2, 0));
+        let edits = sheet.fill_edits(source, target, true);
+        assert_eq!(inputs(edits), vec![(2, 0, "199".to_owned())]);
+        let edits = sheet.fill_edits(source, target, false);
+        assert_eq!(inputs(edits), vec![(2, 0, "1".to_owned())]);
+
+        // Formulas are repeated with their references moved, also in series
+        let source = Selection::cell(0, 3);
+        let edits = sheet.fill_edits(source, source.extend((5, 3)), true);
+        assert_eq!(
+            inputs(edits),
+            vec![(1, 3, "=A2 * 2".to_owned()), (2, 3, "=A3 * 2".to_owned())]
+        );
+        let source = Selection::cell(1, 3);
+        let edits = sheet.fill_edits(source, source.extend((0, 3)), false);
+        assert_eq!(inputs(edits), vec![(0, 3, "-2.25".to_owned())]);
+
+        // Filled right the text of B1 repeats and doesn't fit the price column, the cell
+        // is highlighted instead
+        let source = Selection::cell(0, 1);
+        assert!(sheet
+            .fill_edits(source, source.extend((0, 2)), true)
+            .is_empty());
+        assert!(sheet.cell_error(0, 2).is_some());
+        // Filled left A1 would become a reference left of column A
+        let source = Selection::cell(0, 3);
+        assert!(sheet
+            .fill_edits(source, Selection::cell(0, 0).extend((0, 3)), false)
+            .is_empty());
+        assert_eq!(
+            sheet.cell_error(0, 0).as_deref(),
+            Some("a reference would leave the sheet")
+        );
+    }
+
+    #[test]
+    fn test_sheet_replace() {
+        let mut sheet = sheet(Rows::Loaded(example_rows()));
+        sheet.set_formulas(vec![(3, "text".to_owned(), "=\"text\" & A1".to_owned())]);
+        let search = Search::new("text", SearchOptions::default()).unwrap();
+        let found = sheet.find(&search);
+        assert_eq!(found, vec![(1, 1), (2, 1)]);
+
+        let edits = sheet.replace_edits(&search, "note", &found);
+        assert_eq!(edits[0].value, Value::Text("another note".to_owned()));
+        assert_eq!(edits[1].formula.as_deref(), Some("=\"note\" & A1"));
+
+        let options = SearchOptions {
+            regex: true,
+            ..Default::default()
+        };
+        let search = Search::new(r"^-?(\d)\d*$", options).unwrap();
+        let found = sheet.find(&search);
+        assert_eq!(found, vec![(0, 0), (1, 0), (2, 0)]);
+        // Unchanged cells are left out, text in a number column is an error
+        let edits = sheet.replace_edits(&search, "$1", &found);
+        assert_eq!(edits.len(), 2);
+        assert!(sheet.replace_edits(&search, "x", &found).is_empty());
+        assert!(sheet.cell_error(0, 0).is_some());
+    }
+
+    #[test]
+    fn test_sheet_rules() {
+        let mut sheet = sheet(Rows::Loaded(example_rows()));
+        sheet
+            .rules
+            .insert("number".to_owned(), Rule::parse("min 0").unwrap());
+        sheet.rules.insert(
+            "text".to_owned(),
+            Rule::parse("values test, other").unwrap(),
+        );
+        assert_eq!(sheet.allowed_values(1), ["test", "other"]);
+        assert!(sheet.allowed_values(0).is_empty());
+        // Written before the rule
+        assert_eq!(sheet.cell_error(2, 0).unwrap(), "Must be at least 0");
+        assert_eq!(sheet.cell_error(0, 0), None);
+
+        let edits = vec![
+            sheet.parse_edit(0, 0, "-1").unwrap(),
+            sheet.parse_edit(0, 1, "other").unwrap(),
+            sheet.parse_edit(1, 1, "more").unwrap(),
+        ];
+        let valid = sheet.validate_edits(&edits);
+        assert_eq!(valid, vec![edits[1].clone()]);
+        assert_eq!(sheet.cell_error(0, 0).unwrap(), "Must be at least 0");
+        assert!(sheet
+            .cell_error(1, 1)
+            .unwrap()
+            .starts_with("Must be one of"));
+
+        // Sent to receivers
+        let incoming = Sheet::from_json(&sheet.to_json()).unwrap();
+        assert_eq!(incoming.rules, sheet.rules);
+
+        // A valid edit clears the refusal
+        let edit = sheet.parse_edit(0, 0, "5").unwrap();
+        assert_eq!(sheet.validate_edits(&[edit]).len(), 1);
+        assert_eq!(sheet.cell_error(0, 0), None);
+    }
+
+    #[test]
+    fn test_sheet_formats() {
+        let mut sheet = sheet(Rows::Loaded(example_rows()));
+        let formats = [
+            ("price", "< 0 => background red"),
+            ("text", "~ TEXT => bold"),
+            ("A1:B2", "formula =A1 > 50 => italic"),
+            ("number", "= 100 => background #00FF00"),
+            ("number", "> 10 => background #0000FF, color white"),
+            ("ratio", "scale #000000 #FFFFFF"),
+        ];
+        sheet.formats = parse_formats(
+            formats
+                .iter()
+                .map(|(target, format)| (target.to_string(), format.to_string()))
+                .collect(),
+        );
+        sheet.recalculate();
+        let bounds = sheet.scale_bounds();
+        assert_eq!(bounds[5], Some((-2.25, 0.5)));
+        let style = |row, col| sheet.cell_style(row, col, &bounds);
+
+        assert_eq!(style(0, 0), Style::default());
+        assert_eq!(style(1, 2).background, Some(0xC00000));
+        assert!(!style(0, 1).bold);
+        assert!(style(1, 1).bold);
+        // Later formats win, the formula refers to A2 from the second row
+        assert_eq!(
+            style(1, 0),
+            Style {
+                background: Some(0x0000FF),
+                foreground: Some(0xFFFFFF),
+                bold: false,
+                italic: true,
+            }
+        );
+        // Outside of the range
+        assert!(!style(2, 0).italic);
+        assert_eq!(style(0, 3).background, Some(0xFFFFFF));
+        assert_eq!(style(1, 3).background, Some(0x000000));
+        assert_eq!(style(2, 3).background, None);
+
+        // Sent to receivers
+        let incoming = Sheet::from_json(&sheet.to_json()).unwrap();
+        assert_eq!(incoming.formats, sheet.formats);
+    }
+
+    #[test]
+    fn test_sheet_paged_json() {
+        // The sender has part of the first page and the first row of the third in memory
+        let mut rows: Vec<(usize, Row)> = example_rows().into_iter().enumerate().collect();
+        let last = rows.pop().unwrap().1;
+        rows.push((1000, last));
+        let sheet = sheet(Rows::Paged(PageCache::received(
+            1200,
+            View::default(),
+            rows,
+        )));
+        let json = sheet.to_json();
+        assert_eq!(json["row_count"], 1200);
+        assert_eq!(json["rows"][2]["index"], 1000);
+
+        // Receivers put the rows where they were
+        let incoming = Sheet::from_json(&json).unwrap();
+        assert_eq!(incoming.n_rows(), 1200);
+        assert_eq!(incoming.row(1).unwrap().id, 2);
+        assert_eq!(incoming.row(2), None);
+        assert_eq!(incoming.row(1000).unwrap().id, 3);
+        assert_eq!(incoming.value(1000, 0), &Value::Integer(-3234));
+    }
+
+    #[test]
+    fn test_sheet_receive() {
+        let mut sheet = sheet(Rows::Loaded(example_rows()));
+        sheet.set_formulas(vec![
+            (1, "ratio".to_owned(), "=A2 + 1".to_owned()),
+            (2, "ratio".to_owned(), "=D1 * 2".to_owned()),
+            (3, "ratio".to_owned(), "=A3".to_owned()),
+        ]);
+        assert_eq!(sheet.display_value(1, 3), Value::Integer(202));
+
+        // A2 changes on the sender, only the formulas depending on it are computed
+        let mut incoming = Sheet::from_json(&sheet.to_json()).unwrap();
+        incoming.set_value(1, 0, Value::Integer(5));
+        sheet
+            .formulas
+            .get_mut(&(3, "ratio".to_owned()))
+            .unwrap()
+            .result = Ok(Value::Null);
+        sheet.receive(incoming, &View::default());
+        assert_eq!(sheet.display_value(0, 3), Value::Integer(6));
+        assert_eq!(sheet.display_value(1, 3), Value::Integer(12));
+        assert_eq!(sheet.display_value(2, 3), Value::Null);
+
+        // A new formula closing a cycle
+        let mut incoming = Sheet::from_json(&sheet.to_json()).unwrap();
+        incoming
+            .formulas
+            .insert((2, "number".to_owned()), Formula::parse("=D2").unwrap());
+        sheet.receive(incoming, &View::default());
+        let cycle = Value::Text("#CYCLE!".to_owned());
+        assert_eq!(sheet.display_value(0, 3), cycle);
+        assert_eq!(sheet.display_value(1, 3), cycle);
+        assert_eq!(sheet.display_value(1, 0), cycle);
+
+        // Sorted differently everything is computed again, the references still point to
+        // the same rows
+        let incoming = Sheet::from_json(&sheet.to_json()).unwrap();
+        let mut view = View::default();
+        view.toggle_sort("number", false);
+        sheet.receive(incoming, &view);
+        assert_eq!(sheet.row(0).unwrap().id, 3);
+        let row = |row_id| sheet.index_of(row_id).unwrap();
+        assert_eq!(sheet.display_value(row(1), 3), cycle);
+        assert_eq!(sheet.display_value(row(2), 3), cycle);
+        assert_eq!(sheet.display_value(row(2), 0), cycle);
+    }
+}
@@ -0,0 +1,390 @@
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+
+use crate::csv::{self, CsvOptions, Record};
+use crate::database::{Column, Row};
+use crate::error::Error;
+use crate::json_file::{self, JsonFormat};
+use crate::validation::Rules;
+use crate::value::Value;
+use crate::view::find_column;
+use crate::workbook::{Format, Workbook};
+
+// Records shown before importing
+pub const PREVIEW_ROWS: usize = 10;
+
+// A value that doesn't fit its target column, the record is skipped
+#[derive(Debug, PartialEq, Clone)]
+pub struct ImportError {
+    pub line: usize,
+    pub column: String,
+    pub message: String,
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.column.is_empty() {
+            write!(f, "line {}: {}", self.line, self.message)
+        } else {
+            write!(f, "line {}, {}: {}", self.line, self.column, self.message)
+        }
+    }
+}
+
+// Imported records parsed against the columns of the table, ready for `Database::insert_rows`
+#[derive(Debug, Default, PartialEq)]
+pub struct ImportPlan {
+    pub columns: Vec<Column>,
+    // Source field of every column, columns without one are left NULL
+    pub sources: Vec<Option<usize>>,
+    pub header: bool,
+    pub rows: Vec<Row>,
+    pub errors: Vec<ImportError>,
+    // Header fields that match no column
+    pub ignored: Vec<String>,
+    // Width in pixels the source gave each column, spreadsheet files have them
+    pub widths: Vec<Option<u32>>,
+    // Column rules the values have to pass
+    rules: Rules,
+}
+
+// Parses `field=column` pairs separated by `,`, columns are given by name or header letter
+pub fn parse_mapping(input: &str, columns: &[Column]) -> Result<Vec<(String, String)>, Error> {
+    input
+        .split(',')
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let mut parts = pair.splitn(2, '=').map(str::trim);
+            let (field, label) = match (parts.next(), parts.next()) {
+                (Some(field), Some(label)) if !field.is_empty() => (field, label),
+                _ => return Err(Error::new(&format!("Invalid mapping \"{}\"", pair))),
+            };
+            let index = find_column(columns, label)
+                .ok_or_else(|| Error::new(&format!("Unknown column \"{}\"", label)))?;
+            Ok((field.to_owned(), columns[index].name.clone()))
+        })
+        .collect()
+}
+
+// Column a header field goes to, mapped fields first
+fn target_column(name: &str, columns: &[Column], mapping: &[(String, String)]) -> Option<usize> {
+    let name = name.trim();
+    match mapping.iter().find(|(field, _)| field == name) {
+        Some((_, column)) => find_column(columns, column),
+        None => find_column(columns, name),
+    }
+}
+
+// A header names the columns, or has a field that doesn't parse where the next record's does
+fn detect_header(records: &[Record], columns: &[Column], mapping: &[(String, String)]) -> bool {
+    let first = match records.first() {
+        Some(first) => &first.fields,
+        None => return false,
+    };
+    let names = first
+        .iter()
+        .filter(|field| !field.trim().is_empty())
+        .all(|field| target_column(field, columns, mapping).is_some());
+    if names && first.iter().any(|field| !field.trim().is_empty()) {
+        return true;
+    }
+    let second = match records.get(1) {
+        Some(second) => &second.fields,
+        None => return false,
+    };
+    columns.iter().enumerate().any(|(index, column)| {
+        let parses = |fields: &Vec<String>| {
+            fields
+                .get(index)
+                .into_iter()
+                .all(|field| column.kind.parse(field).is_ok())
+        };
+        !parses(first) && parses(second)
+    })
+}
+
+impl ImportPlan {
+    // `header` None detects it. With a header fields go to the columns `mapping` gives or
+    // the ones they name (by name or header letter), without one to the columns at the same
+    // position.
+    pub fn new(
+        records: &[Record],
+        header: Option<bool>,
+        columns: &[Column],
+        mapping: &[(String, String)],
+    ) -> ImportPlan {
+        ImportPlan::with_rules(records, header, columns, mapping, &Rules::new())
+    }
+
+    // Records breaking a rule of their column are skipped like ones that don't parse
+    pub fn with_rules(
+        records: &[Record],
+        header: Option<bool>,
+        columns: &[Column],
+        mapping: &[(String, String)],
+        rules: &Rules,
+    ) -> ImportPlan {
+        let header = header.unwrap_or_else(|| detect_header(records, columns, mapping));
+        let mut plan = ImportPlan {
+            columns: columns.to_vec(),
+            sources: vec![None; columns.len()],
+            header,
+            widths: vec![None; columns.len()],
+            rules: rules.clone(),
+            ..Default::default()
+        };
+        let body = match records.split_first() {
+            Some((first, rest)) if header => {
+                for (field, name) in first.fields.iter().enumerate() {
+                    match target_column(name, columns, mapping) {
+                        Some(index) if plan.sources[index].is_none() => {
+                            plan.sources[index] = Some(field)
+                        }
+                        _ => plan.ignored.push(name.clone()),
+                    }
+                }
+                rest
+            }
+            _ => {
+                let width = records.iter().map(|record| record.fields.len()).max();
+                for (index, source) in plan.sources.iter_mut().enumerate() {
+                    if index < width.unwrap_or_default() {
+                        *source = Some(index);
+                    }
+                }
+                records
+            }
+        };
+        for record in body {
+            match plan.parse_record(record) {
+                Ok(row) => plan.rows.push(row),
+                Err(error) => plan.errors.push(error),
+            }
+        }
+        plan
+    }
+
+    // Missing fields are NULL
+    fn parse_record(&self, record: &Record) -> Result<Row, ImportError> {
+        if !self.header && record.fields.len() > self.columns.len() {
+            return Err(ImportError {
+                line: record.line,
+                column: String::new(),
+                message: format!(
+                    "{} fields, the table has {} columns",
+                    record.fields.len(),
+                    self.columns.len()
+                ),
+            });
+        }
+        let mut values = Vec::with_capacity(self.columns.len());
+        for (column, source) in self.columns.iter().zip(self.sources.iter()) {
+            let field = source.and_then(|source| record.fields.get(source));
+            let error = |error: Error| ImportError {
+                line: record.line,
+                column: column.name.clone(),
+                message: error.details,
+            };
+            let value = match field {
+                Some(field) => column.kind.parse(field).map_err(error)?,
+                None => Value::Null,
+            };
+            if let Some(rule) = self.rules.get(&column.name) {
+                rule.check(&value).map_err(error)?;
+            }
+            values.push(value);
+        }
+        Ok(Row {
+            values,
+            ..Default::default()
+        })
+    }
+
+    // The first rows as they will be inserted
+    pub fn preview(&self, limit: usize) -> String {
+        let mut lines = vec![self
+            .columns
+            .iter()
+            .map(|column| column.name.as_str())
+            .collect::<Vec<&str>>()
+            .join(" | ")];
+        for row in self.rows.iter().take(limit) {
+            let values: Vec<String> = row.values.iter().map(Value::to_string).collect();
+            lines.push(values.join(" | "));
+        }
+        if self.rows.len() > limit {
+            lines.push(format!("... {} more", self.rows.len() - limit));
+        }
+        lines.join("\n")
+    }
+
+    // What will be imported and what not, `limit` caps the listed errors
+    pub fn report(&self, limit: usize) -> String {
+        let mut lines = vec![format!(
+            "{} rows to import, {} skipped{}",
+            self.rows.len(),
+            self.errors.len(),
+            if self.header {
+                ", first line is a header"
+            } else {
+                ""
+            }
+        )];
+        if !self.ignored.is_empty() {
+            lines.push(format!("Ignored fields: {}", self.ignored.join(", ")));
+        }
+        lines.extend(self.errors.iter().take(limit).map(ImportError::to_string));
+        if self.errors.len() > limit {
+            lines.push(format!("... {} more errors", self.errors.len() - limit));
+        }
+        lines.join("\n")
+    }
+}
+
+// Header with the column names, then the values; NULL is empty
+pub fn export_records(columns: &[Column], rows: &[Row]) -> Vec<Vec<String>> {
+    let header = columns.iter().map(|column| column.name.clone()).collect();
+    let values = rows
+        .iter()
+        .map(|row| row.values.iter().map(Value::to_string).collect());
+    std::iter::once(header).chain(values).collect()
+}
+
+// Prefixes errors with the file they are about
+fn file_error<E: Into<Error>>(path: &str) -> impl Fn(E) -> Error + '_ {
+    move |error| Error::new(&format!("{}: {}", path, error.into().details))
+}
+
+// Reads a file into an import plan for the columns and their rules, nothing is written yet.
+// .xlsx and .ods files are read as spreadsheets, .json and .ndjson as objects keyed by
+// field name (always with a header), anything else as CSV.
+pub fn import_file(
+    path: &str,
+    options: &CsvOptions,
+    mapping: &[(String, String)],
+    columns: &[Column],
+    rules: &Rules,
+) -> Result<ImportPlan, Error> {
+    let mut header = options.header;
+    let workbook = match (Format::from_path(path), JsonFormat::from_path(path)) {
+        (Some(format), _) => {
+            let file = File::open(path).map_err(file_error(path))?;
+            Workbook::read(file, format).map_err(file_error(path))?
+        }
+        (None, Some(format)) => {
+            let file = File::open(path).map_err(file_error(path))?;
+            header = Some(true);
+            Workbook {
+                records: json_file::read(BufReader::new(file), format).map_err(file_error(path))?,
+                ..Default::default()
+            }
+        }
+        (None, None) => {
+            let text = fs::read_to_string(path).map_err(file_error(path))?;
+            Workbook {
+                records: csv::read(&text, options)?,
+                ..Default::default()
+            }
+        }
+    };
+    let mut plan = ImportPlan::with_rules(&workbook.records, header, columns, mapping, rules);
+    plan.widths = plan
+        .sources
+        .iter()
+        .map(|source| source.and_then(|source| workbook.widths.get(source).copied().flatten()))
+        .collect();
+    Ok(plan)
+}
+
+// Writes the rows in the format the extension asks for, `widths` are the column widths
+// in pixels (only spreadsheets have them)
+pub fn export_file(
+    path: &str,
+    options: &CsvOptions,
+    columns: &[Column],
+    rows: &[Row],
+    widths: &[u32],
+) -> Result<(), Error> {
+    match (Format::from_path(path), JsonFormat::from_path(path)) {
+        (Some(format), _) => {
+            let file = File::create(path).map_err(file_error(path))?;
+            Workbook::write(file, format, columns, rows, widths).map_err(file_error(path))?;
+        }
+        (None, Some(format)) => {
+            let file = File::create(path).map_err(file_error(path))?;
+            json_file::write(BufWriter::new(file), format, columns, rows)
+                .map_err(file_error(path))?;
+        }
+        (None, None) => {
+            let text = csv::write(&export_records(columns, rows), options);
+            fs::write(path, text).map_err(file_error(path))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::csv::{read, write, CsvOptions};
+    use crate::database::{example_columns, example_rows};
+    use crate::transfer::{export_records, ImportPlan};
+    use crate::validation::{Rule, Rules};
+    use crate::value::Value;
+
+    #[test]
+    fn test_import_plan() {
+        let columns = example_columns();
+        let options = CsvOptions::default();
+
+        // Exported rows come back with the same values
+        let text = write(&export_records(&columns, &example_rows()), &options);
+        let plan = ImportPlan::new(&read(&text, &options).unwrap(), None, &columns, &[]);
+        assert!(plan.header);
+        assert!(plan.errors.is_empty());
+        let values: Vec<Vec<Value>> = plan.rows.into_iter().map(|row| row.values).collect();
+        let expected: Vec<Vec<Value>> = example_rows().into_iter().map(|row| row.values).collect();
+        assert_eq!(values, expected);
+
+        // Fields are matched by name, unknown ones ignored and bad values reported
+        let text = "Text,B,number,other\nx,,1,?\ny,,many,?\n";
+        let plan = ImportPlan::new(&read(text, &options).unwrap(), None, &columns, &[]);
+        assert_eq!(plan.sources[..3], [Some(2), Some(1), None]);
+        assert_eq!(plan.ignored, vec!["Text", "other"]);
+        assert_eq!(plan.rows.len(), 1);
+        assert_eq!(plan.rows[0].values[0], Value::Integer(1));
+        assert_eq!(plan.rows[0].values[1], Value::Null);
+        assert_eq!(plan.errors.len(), 1);
+        assert_eq!(plan.errors[0].line, 3);
+        assert_eq!(plan.errors[0].column, "number");
+
+        // Without a header fields go by position
+        let plan = ImportPlan::new(
+            &read("5,five\n6,six\n", &options).unwrap(),
+            None,
+            &columns,
+            &[],
+        );
+        assert!(!plan.header);
+        assert_eq!(plan.rows.len(), 2);
+        assert_eq!(plan.rows[1].values[1], Value::Text("six".to_owned()));
+        assert_eq!(plan.rows[1].values[2], Value::Null);
+
+        // Values breaking a column rule skip their record
+        let mut rules = Rules::new();
+        rules.insert("number".to_owned(), Rule::parse("max 5").unwrap());
+        rules.insert("price".to_owned(), Rule::parse("required").unwrap());
+        let records = read("number,price\n5,1\n6,1\n4,\n", &options).unwrap();
+        let plan = ImportPlan::with_rules(&records, None, &columns, &[], &rules);
+        assert_eq!(plan.rows.len(), 1);
+        let errors: Vec<String> = plan.errors.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            errors,
+            vec![
+                "line 3, number: Must be at most 5",
+                "line 4, price: A value is required"
+            ]
+        );
+    }
+}
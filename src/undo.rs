@@ -0,0 +1,448 @@
+use crate::database::{CellUpdate, Database, Row};
+use crate::draw_table::cell_name;
+use crate::error::Error;
+use crate::formula::Formula;
+use crate::sheet::{describe_error, CellEdit, Rows, Sheet};
+use crate::value::Value;
+
+// Older commands are forgotten
+const MAX_COMMANDS: usize = 100;
+
+// Cells are found by row id and column name, positions change with sorting and schema edits
+#[derive(Debug, PartialEq, Clone)]
+pub struct CellChange {
+    pub row_id: i32,
+    pub column: String,
+    pub old_value: Value,
+    pub new_value: Value,
+    pub old_formula: Option<String>,
+    pub new_formula: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Command {
+    // One or more cells written together, e.g. a single edit or a paste
+    EditCells(Vec<CellChange>),
+    AddRow(Row),
+    // The row with its formulas as (column name, formula)
+    DeleteRow(Row, Vec<(String, String)>),
+}
+
+impl Command {
+    // Has to be built before the edits are applied to capture the old values
+    pub fn edit_cells(sheet: &Sheet, edits: &[CellEdit]) -> Command {
+        let changes = edits
+            .iter()
+            .filter_map(|edit| {
+                let row = sheet.row(edit.row)?;
+                let column = sheet.columns.get(edit.col)?;
+                Some(CellChange {
+                    row_id: row.id,
+                    column: column.name.clone(),
+                    old_value: sheet.value(edit.row, edit.col).clone(),
+                    new_value: edit.value.clone(),
+                    old_formula: sheet
+                        .formula(edit.row, edit.col)
+                        .map(|formula| formula.source.clone()),
+                    new_formula: edit.formula.clone(),
+                })
+            })
+            .collect();
+        Command::EditCells(changes)
+    }
+
+    // Like `edit_cells` for updates of rows that don't have to be in memory, made from the
+    // given rows
+    pub fn edit_rows(sheet: &Sheet, updates: &[(Row, CellUpdate)]) -> Command {
+        let changes = updates
+            .iter()
+            .filter_map(|(row, update)| {
+                let col = sheet
+                    .columns
+                    .iter()
+                    .position(|column| *column == update.column)?;
+                let key = (row.id, update.column.name.clone());
+                Some(CellChange {
+                    row_id: row.id,
+                    column: update.column.name.clone(),
+                    old_value: row.values.get(col).cloned().unwrap_or(Value::Null),
+                    new_value: update.value.clone(),
+                    old_formula: sheet
+                        .formulas
+                        .get(&key)
+                        .map(|formula| formula.source.clone()),
+                    new_formula: update.formula.clone(),
+                })
+            })
+            .collect();
+        Command::EditCells(changes)
+    }
+
+    // Has to be built before the row is deleted, which drops its formulas
+    pub fn delete_row(sheet: &Sheet, row: &Row) -> Command {
+        let formulas = sheet
+            .formulas
+            .iter()
+            .filter(|((row_id, _), _)| *row_id == row.id)
+            .map(|((_, column), formula)| (column.clone(), formula.source.clone()))
+            .collect();
+        Command::DeleteRow(row.clone(), formulas)
+    }
+
+    // Re-inserted rows get a new id
+    fn rename_row(&mut self, old_id: i32, new_id: i32) {
+        match self {
+            Command::EditCells(changes) => changes
+                .iter_mut()
+                .filter(|change| change.row_id == old_id)
+                .for_each(|change| change.row_id = new_id),
+            Command::AddRow(row) | Command::DeleteRow(row, _) => {
+                if row.id == old_id {
+                    row.id = new_id;
+                }
+            }
+        }
+    }
+}
+
+// Undo and redo act on the database and the sheet, like the edits they revert
+#[derive(Default, Debug)]
+pub struct UndoStack {
+    undo: Vec<Command>,
+    redo: Vec<Command>,
+}
+
+impl UndoStack {
+    pub fn push(&mut self, command: Command) {
+        self.redo.clear();
+        self.undo.push(command);
+        if self.undo.len() > MAX_COMMANDS {
+            self.undo.remove(0);
+        }
+    }
+
+    // Commands can't be replayed on another database
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+
+    // Returns false if there was nothing to undo
+    pub fn undo(&mut self, db: &Database, sheet: &mut Sheet) -> Result<bool, Error> {
+        self.step(db, sheet, true)
+    }
+
+    // Returns false if there was nothing to redo
+    pub fn redo(&mut self, db: &Database, sheet: &mut Sheet) -> Result<bool, Error> {
+        self.step(db, sheet, false)
+    }
+
+    fn step(&mut self, db: &Database, sheet: &mut Sheet, undo: bool) -> Result<bool, Error> {
+        let popped = if undo {
+            self.undo.pop()
+        } else {
+            self.redo.pop()
+        };
+        let command = match popped {
+            Some(command) => command,
+            None => return Ok(false),
+        };
+        // A failed command stays where it was, so it can be retried
+        let result = self.run(db, sheet, &command, undo);
+        let (from, to) = if undo {
+            (&mut self.undo, &mut self.redo)
+        } else {
+            (&mut self.redo, &mut self.undo)
+        };
+        match result {
+            Ok(command) => {
+                to.push(command);
+                Ok(true)
+            }
+            Err(error) => {
+                from.push(command);
+                Err(error)
+            }
+        }
+    }
+
+    // Returns the command with updated row ids
+    fn run(
+        &mut self,
+        db: &Database,
+        sheet: &mut Sheet,
+        command: &Command,
+        undo: bool,
+    ) -> Result<Command, Error> {
+        match (command, undo) {
+            // Big tables only have some rows in memory, the others are read from the database
+            (Command::EditCells(changes), _) if matches!(sheet.rows, Rows::Paged(_)) => {
+                let ids: Vec<i32> = changes.iter().map(|change| change.row_id).collect();
+                let rows = db.get_rows_by_id(&sheet.columns, &ids)?;
+                let mut updates = Vec::with_capacity(changes.len());
+                for change in changes.iter() {
+                    let (expected, value, formula) = if undo {
+                        (&change.new_value, &change.old_value, &change.old_formula)
+                    } else {
+                        (&change.old_value, &change.new_value, &change.new_formula)
+                    };
+                    let row = rows
+                        .iter()
+                        .find(|row| row.id == change.row_id)
+                        .ok_or_else(|| Error::new("The row is not in the table anymore"))?;
+                    let col = sheet
+                        .columns
+                        .iter()
+                        .position(|column| column.name == change.column)
+                        .ok_or_else(|| {
+                            Error::new(&format!("Column \"{}\" no longer exists", change.column))
+                        })?;
+                    if row.values.get(col).unwrap_or(&Value::Null) != expected {
+                        return Err(Error::new(&format!(
+                            "Column \"{}\" of row {} was changed since",
+                            change.column, change.row_id
+                        )));
+                    }
+                    updates.push(CellUpdate {
+                        row_id: row.id,
+                        version: row.version,
+                        column: sheet.columns[col].clone(),
+                        value: value.clone(),
+                        formula: formula.clone(),
+                    });
+                }
+                sheet
+                    .apply_updates(db, &updates)
+                    .map_err(|error| Error::new(&error.to_string()))?;
+                Ok(command.clone())
+            }
+            (Command::EditCells(changes), _) => {
+                let mut edits = Vec::with_capacity(changes.len());
+                for change in changes.iter() {
+                    let (expected, value, formula) = if undo {
+                        (&change.new_value, &change.old_value, &change.old_formula)
+                    } else {
+                        (&change.old_value, &change.new_value, &change.new_formula)
+                    };
+                    let row = sheet
+                        .index_of(change.row_id)
+                        .ok_or_else(|| Error::new("The row is not in the sheet anymore"))?;
+                    let col = sheet
+                        .columns
+                        .iter()
+                        .position(|column| column.name == change.column)
+                        .ok_or_else(|| {
+                            Error::new(&format!("Column \"{}\" no longer exists", change.column))
+                        })?;
+                    // Don't silently overwrite what others wrote since
+                    if sheet.value(row, col) != expected {
+                        return Err(Error::new(&format!(
+                            "{} was changed since",
+                            cell_name(row, col)
+                        )));
+                    }
+                    edits.push(CellEdit {
+                        row,
+                        col,
+                        value: value.clone(),
+                        formula: formula.clone(),
+                    });
+                }
+                sheet
+                    .apply_edits(db, &edits)
+                    .map_err(|error| Error::new(&describe_error(&edits, &error)))?;
+                Ok(command.clone())
+            }
+            (Command::AddRow(row), true) | (Command::DeleteRow(row, _), false) => {
+                db.delete_row(&sheet.columns, row.id)?;
+                // The change log brings the deletion into the sheet
+                sheet.refresh(db)?;
+                Ok(command.clone())
+            }
+            (Command::AddRow(row), false) | (Command::DeleteRow(row, _), true) => {
+                let formulas = match command {
+                    Command::DeleteRow(_, formulas) => formulas.as_slice(),
+                    _ => &[],
+                };
+                let id = db.restore_row(&sheet.columns, row.clone(), formulas)?;
+                let inserted = Row {
+                    id,
+                    version: 0,
+                    values: row.values.clone(),
+                };
+                sheet.push_row(inserted);
+                if !formulas.is_empty() {
+                    for (column, source) in formulas {
+                        if let Ok(formula) = Formula::parse(source) {
+                            sheet.formulas.insert((id, column.clone()), formula);
+                        }
+                    }
+                    sheet.recalculate();
+                }
+                self.undo
+                    .iter_mut()
+                    .chain(self.redo.iter_mut())
+                    .for_each(|other| other.rename_row(row.id, id));
+                let mut command = command.clone();
+                command.rename_row(row.id, id);
+                Ok(command)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::tests::{get_cached_database, lock_database, reset_database};
+    use crate::database::{example_rows, Database, Row};
+    use crate::sheet::tests::sheet;
+    use crate::sheet::{CellEdit, Rows, Sheet};
+    use crate::undo::{CellChange, Command, UndoStack, MAX_COMMANDS};
+    use crate::value::Value;
+    use crate::view::View;
+
+    // The example rows in the database and the sheet
+    fn example_sheet(db: &Database) -> Sheet {
+        reset_database(db);
+        let columns = db.get_columns().unwrap();
+        db.insert_rows(&columns, example_rows()).unwrap();
+        db.load_sheet(&View::default()).unwrap()
+    }
+
+    fn database_values(db: &Database, sheet: &Sheet) -> Vec<Vec<Value>> {
+        let rows = db.get_rows(&sheet.columns, &View::default()).unwrap();
+        rows.into_iter().map(|row| row.values).collect()
+    }
+
+    fn sheet_values(sheet: &Sheet) -> Vec<Vec<Value>> {
+        (0..sheet.n_rows())
+            .map(|row| sheet.row(row).unwrap().values.clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_edit_cells_command() {
+        let sheet = sheet(Rows::Loaded(example_rows()));
+        let edits = [CellEdit {
+            row: 1,
+            col: 0,
+            value: Value::Integer(5),
+            formula: None,
+        }];
+        let mut command = Command::edit_cells(&sheet, &edits);
+        let change = CellChange {
+            row_id: 2,
+            column: "number".to_owned(),
+            old_value: Value::Integer(100),
+            new_value: Value::Integer(5),
+            old_formula: None,
+            new_formula: None,
+        };
+        assert_eq!(command, Command::EditCells(vec![change.clone()]));
+
+        command.rename_row(2, 7);
+        assert_eq!(
+            command,
+            Command::EditCells(vec![CellChange {
+                row_id: 7,
+                ..change
+            }])
+        );
+    }
+
+    #[test]
+    fn test_undo_stack_limit() {
+        let mut stack = UndoStack::default();
+        stack.redo.push(Command::AddRow(Row::default()));
+        for id in 0..MAX_COMMANDS as i32 + 5 {
+            stack.push(Command::DeleteRow(
+                Row {
+                    id,
+                    ..Default::default()
+                },
+                Vec::new(),
+            ));
+        }
+        // A new command makes the undone ones unreachable
+        assert!(stack.redo.is_empty());
+        assert_eq!(stack.undo.len(), MAX_COMMANDS);
+        assert_eq!(
+            stack.undo[0],
+            Command::DeleteRow(
+                Row {
+                    id: 5,
+                    ..Default::default()
+                },
+                Vec::new()
+            )
+        );
+    }
+
+    #[test]
+    fn test_database_undo_edit_cells() {
+        let _lock = lock_database();
+        let db = get_cached_database();
+        let mut sheet = example_sheet(db);
+        let before = sheet_values(&sheet);
+        let mut stack = UndoStack::default();
+
+        let edits = [
+            sheet.parse_edit(0, 0, "5").unwrap(),
+            sheet.parse_edit(1, 1, "changed").unwrap(),
+        ];
+        let command = Command::edit_cells(&sheet, &edits);
+        sheet.apply_edits(db, &edits).unwrap();
+        stack.push(command);
+        let after = sheet_values(&sheet);
+        assert_eq!(after[0][0], Value::Integer(5));
+        assert_eq!(database_values(db, &sheet), after);
+
+        assert!(stack.undo(db, &mut sheet).unwrap());
+        assert_eq!(sheet_values(&sheet), before);
+        assert_eq!(database_values(db, &sheet), before);
+
+        assert!(stack.redo(db, &mut sheet).unwrap());
+        assert_eq!(sheet_values(&sheet), after);
+        assert_eq!(database_values(db, &sheet), after);
+        assert!(!stack.redo(db, &mut sheet).unwrap());
+    }
+
+    #[test]
+    fn test_database_undo_delete_row() {
+        let _lock = lock_database();
+        let db = get_cached_database();
+        let mut sheet = example_sheet(db);
+        let mut stack = UndoStack::default();
+
+        // D3 doubles A1
+        let edit = sheet.parse_edit(2, 3, "=A1 * 2").unwrap();
+        sheet.apply_edits(db, &[edit]).unwrap();
+        let deleted = sheet.row(2).unwrap().clone();
+        let command = Command::delete_row(&sheet, &deleted);
+        db.delete_row(&sheet.columns, deleted.id).unwrap();
+        stack.push(command);
+        sheet.refresh(db).unwrap();
+        assert_eq!(sheet.n_rows(), 2);
+        assert!(db.get_formulas().unwrap().is_empty());
+
+        // The row comes back under a new id, with its formula
+        assert!(stack.undo(db, &mut sheet).unwrap());
+        assert_eq!(sheet.n_rows(), 3);
+        let restored = sheet.row(2).unwrap().clone();
+        assert_ne!(restored.id, deleted.id);
+        assert_eq!(restored.values, deleted.values);
+        assert_eq!(
+            db.get_formulas().unwrap(),
+            vec![(restored.id, "ratio".to_owned(), "=A1 * 2".to_owned())]
+        );
+        let formula = sheet.formula(2, 3).unwrap();
+        assert_eq!(formula.source, "=A1 * 2");
+        assert_eq!(formula.result, Ok(Value::Integer(2)));
+        assert_eq!(database_values(db, &sheet), sheet_values(&sheet));
+
+        assert!(stack.redo(db, &mut sheet).unwrap());
+        assert_eq!(sheet.n_rows(), 2);
+        assert_eq!(database_values(db, &sheet), sheet_values(&sheet));
+        assert!(db.get_formulas().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,202 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use regex::Regex;
+
+use crate::error::Error;
+use crate::value::Value;
+
+// Rules by column name
+pub type Rules = BTreeMap<String, Rule>;
+
+// Checks the values of a column have to pass. Written as clauses separated by `;`, e.g.
+// `required; min 0; max 100; length 20; pattern ^[A-Z]+$; values low, medium, high`.
+#[derive(Debug, Default, Clone)]
+pub struct Rule {
+    pub required: bool,
+    // Bounds of numeric values
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    // Characters of the value as text
+    pub max_length: Option<usize>,
+    // Has to match somewhere in the value as text, anchor it to match all of it
+    pub pattern: Option<Regex>,
+    // Values the cell can take, edited with a dropdown
+    pub allowed: Vec<String>,
+}
+
+impl PartialEq for Rule {
+    // Patterns are equal when written the same
+    fn eq(&self, other: &Rule) -> bool {
+        let pattern = |rule: &Rule| rule.pattern.as_ref().map(Regex::as_str).map(str::to_owned);
+        self.required == other.required
+            && self.min == other.min
+            && self.max == other.max
+            && self.max_length == other.max_length
+            && pattern(self) == pattern(other)
+            && self.allowed == other.allowed
+    }
+}
+
+impl Rule {
+    pub fn parse(input: &str) -> Result<Rule, Error> {
+        let mut rule = Rule::default();
+        for clause in input
+            .split(';')
+            .map(str::trim)
+            .filter(|clause| !clause.is_empty())
+        {
+            let (name, argument) = match clause.find(char::is_whitespace) {
+                Some(index) => (&clause[..index], clause[index..].trim()),
+                None => (clause, ""),
+            };
+            let invalid = || Error::new(&format!("Invalid rule \"{}\"", clause));
+            let number = || argument.parse::<f64>().map_err(|_| invalid());
+            match name.to_lowercase().as_str() {
+                "required" if argument.is_empty() => rule.required = true,
+                "min" => rule.min = Some(number()?),
+                "max" => rule.max = Some(number()?),
+                "length" => rule.max_length = Some(argument.parse().map_err(|_| invalid())?),
+                "pattern" if !argument.is_empty() => {
+                    let pattern = Regex::new(argument).map_err(|error| {
+                        Error::new(&format!("Invalid pattern \"{}\": {}", argument, error))
+                    })?;
+                    rule.pattern = Some(pattern);
+                }
+                "values" => {
+                    rule.allowed = argument
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|value| !value.is_empty())
+                        .map(str::to_owned)
+                        .collect();
+                }
+                _ => return Err(invalid()),
+            }
+        }
+        Ok(rule)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Rule::default()
+    }
+
+    // Why the value breaks the rule
+    pub fn check(&self, value: &Value) -> Result<(), Error> {
+        if value.is_null() {
+            return if self.required {
+                Err(Error::new("A value is required"))
+            } else {
+                Ok(())
+            };
+        }
+        if let Some(number) = value.as_f64().filter(|_| value.is_numeric()) {
+            if let Some(min) = self.min.filter(|min| number < *min) {
+                return Err(Error::new(&format!("Must be at least {}", min)));
+            }
+            if let Some(max) = self.max.filter(|max| number > *max) {
+                return Err(Error::new(&format!("Must be at most {}", max)));
+            }
+        }
+        let text = value.to_string();
+        if let Some(max_length) = self.max_length {
+            if text.chars().count() > max_length {
+                return Err(Error::new(&format!(
+                    "Longer than {} characters",
+                    max_length
+                )));
+            }
+        }
+        if let Some(pattern) = &self.pattern {
+            if !pattern.is_match(&text) {
+                return Err(Error::new(&format!(
+                    "Doesn't match the pattern {}",
+                    pattern.as_str()
+                )));
+            }
+        }
+        if !self.allowed.is_empty() && !self.allowed.contains(&text) {
+            return Err(Error::new(&format!(
+                "Must be one of {}",
+                self.allowed.join(", ")
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut clauses = Vec::new();
+        if self.required {
+            clauses.push("required".to_owned());
+        }
+        if let Some(min) = self.min {
+            clauses.push(format!("min {}", min));
+        }
+        if let Some(max) = self.max {
+            clauses.push(format!("max {}", max));
+        }
+        if let Some(max_length) = self.max_length {
+            clauses.push(format!("length {}", max_length));
+        }
+        if let Some(pattern) = &self.pattern {
+            clauses.push(format!("pattern {}", pattern.as_str()));
+        }
+        if !self.allowed.is_empty() {
+            clauses.push(format!("values {}", self.allowed.join(", ")));
+        }
+        write!(f, "{}", clauses.join("; "))
+    }
+}
+
+// Parses (column name, rule) pairs, rules that don't parse are dropped
+pub fn parse_rules(rules: Vec<(String, String)>) -> Rules {
+    rules
+        .into_iter()
+        .filter_map(|(column, rule)| Some((column, Rule::parse(&rule).ok()?)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::validation::Rule;
+    use crate::value::Value;
+
+    #[test]
+    fn test_rule() {
+        let rule = Rule::parse("required; min 0; max 10.5").unwrap();
+        assert!(rule.check(&Value::Integer(0)).is_ok());
+        assert!(rule.check(&Value::Decimal("10.50".to_owned())).is_ok());
+        assert!(rule.check(&Value::Integer(-1)).is_err());
+        assert!(rule.check(&Value::Float(11.0)).is_err());
+        assert_eq!(
+            rule.check(&Value::Null).unwrap_err().details,
+            "A value is required"
+        );
+
+        let rule = Rule::parse("length 3; pattern ^[A-Z]+$").unwrap();
+        assert!(rule.check(&Value::Text("ABC".to_owned())).is_ok());
+        assert!(rule.check(&Value::Text("ABCD".to_owned())).is_err());
+        assert!(rule.check(&Value::Text("Ab".to_owned())).is_err());
+        // Not required, NULL passes
+        assert!(rule.check(&Value::Null).is_ok());
+
+        let rule = Rule::parse("values low, medium,high").unwrap();
+        assert_eq!(rule.allowed, ["low", "medium", "high"]);
+        assert!(rule.check(&Value::Text("medium".to_owned())).is_ok());
+        assert_eq!(
+            rule.check(&Value::Text("none".to_owned()))
+                .unwrap_err()
+                .details,
+            "Must be one of low, medium, high"
+        );
+
+        let text = "required; min -1; length 5; pattern \\d; values 1, 2";
+        assert_eq!(Rule::parse(text).unwrap().to_string(), text);
+        assert!(Rule::parse("").unwrap().is_empty());
+        assert!(Rule::parse("min five").is_err());
+        assert!(Rule::parse("pattern (").is_err());
+        assert!(Rule::parse("unique").is_err());
+    }
+}
@@ -0,0 +1,461 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+use mysql::serde_json::{json, Value as JsonValue};
+
+use crate::error::Error;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ColumnType {
+    Integer,
+    Float,
+    Decimal,
+    Boolean,
+    Date,
+    Timestamp,
+    Text,
+}
+
+impl ColumnType {
+    pub const ALL: [ColumnType; 7] = [
+        ColumnType::Integer,
+        ColumnType::Float,
+        ColumnType::Decimal,
+        ColumnType::Boolean,
+        ColumnType::Date,
+        ColumnType::Timestamp,
+        ColumnType::Text,
+    ];
+
+    // Maps the `Type` column of `SHOW COLUMNS` to a column type
+    pub fn from_sql_type(sql_type: &str) -> ColumnType {
+        let sql_type = sql_type.to_lowercase();
+        if sql_type.starts_with("tinyint(1)") || sql_type.starts_with("bit(1)") {
+            ColumnType::Boolean
+        } else if sql_type.contains("int") {
+            ColumnType::Integer
+        } else if sql_type.starts_with("decimal") || sql_type.starts_with("numeric") {
+            ColumnType::Decimal
+        } else if sql_type.starts_with("float")
+            || sql_type.starts_with("double")
+            || sql_type.starts_with("real")
+        {
+            ColumnType::Float
+        } else if sql_type == "date" {
+            ColumnType::Date
+        } else if sql_type.starts_with("datetime") || sql_type.starts_with("timestamp") {
+            ColumnType::Timestamp
+        } else {
+            ColumnType::Text
+        }
+    }
+
+    pub fn sql_type(&self) -> &'static str {
+        match self {
+            ColumnType::Integer => "BIGINT",
+            ColumnType::Float => "DOUBLE",
+            ColumnType::Decimal => "DECIMAL(20,6)",
+            ColumnType::Boolean => "BOOLEAN",
+            ColumnType::Date => "DATE",
+            ColumnType::Timestamp => "DATETIME",
+            ColumnType::Text => "TEXT",
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ColumnType::Integer => "integer",
+            ColumnType::Float => "float",
+            ColumnType::Decimal => "decimal",
+            ColumnType::Boolean => "boolean",
+            ColumnType::Date => "date",
+            ColumnType::Timestamp => "timestamp",
+            ColumnType::Text => "text",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<ColumnType> {
+        ColumnType::ALL
+            .iter()
+            .copied()
+            .find(|kind| kind.name() == name)
+    }
+
+    // Empty input is NULL, blank input is NULL for everything but text
+    pub fn parse(&self, input: &str) -> Result<Value, Error> {
+        let trimmed = input.trim();
+        if input.is_empty() || (trimmed.is_empty() && *self != ColumnType::Text) {
+            return Ok(Value::Null);
+        }
+        let error = || Error::new(format!("Can't parse \"{}\" as {}", input, self.name()).as_str());
+        match self {
+            ColumnType::Integer => trimmed.parse().map(Value::Integer).map_err(|_| error()),
+            ColumnType::Float => match trimmed.parse::<f64>() {
+                Ok(value) if value.is_finite() => Ok(Value::Float(value)),
+                _ => Err(error()),
+            },
+            ColumnType::Decimal => parse_decimal(trimmed).map(Value::Decimal).ok_or_else(error),
+            ColumnType::Boolean => match trimmed.to_lowercase().as_str() {
+                "1" | "true" | "yes" | "y" => Ok(Value::Boolean(true)),
+                "0" | "false" | "no" | "n" => Ok(Value::Boolean(false)),
+                _ => Err(error()),
+            },
+            ColumnType::Date => Date::parse(trimmed).map(Value::Date).ok_or_else(error),
+            ColumnType::Timestamp => DateTime::parse(trimmed)
+                .map(Value::Timestamp)
+                .ok_or_else(error),
+            ColumnType::Text => Ok(Value::Text(input.to_owned())),
+        }
+    }
+
+    // Values read back from MySQL are trusted, so unexpected data is kept as text
+    pub fn decode_mysql(&self, value: mysql::Value) -> Value {
+        let raw = match value {
+            mysql::Value::NULL => return Value::Null,
+            mysql::Value::Bytes(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+            mysql::Value::Int(value) => value.to_string(),
+            mysql::Value::UInt(value) => value.to_string(),
+            mysql::Value::Date(year, month, day, hour, minute, second, _) => format!(
+                "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+                year, month, day, hour, minute, second
+            ),
+            other => other.as_sql(true).trim_matches('\'').to_owned(),
+        };
+        let raw = match self {
+            ColumnType::Date => raw.split(' ').next().unwrap_or_default().to_owned(),
+            _ => raw,
+        };
+        self.parse(&raw).unwrap_or(Value::Text(raw))
+    }
+
+    pub fn decode_json(&self, value: &JsonValue) -> Result<Value, Error> {
+        match value {
+            JsonValue::Null => Ok(Value::Null),
+            JsonValue::Bool(value) if *self == ColumnType::Boolean => Ok(Value::Boolean(*value)),
+            JsonValue::String(value) => self.parse(value),
+            other => self.parse(&other.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum Value {
+    #[default]
+    Null,
+    Integer(i64),
+    Float(f64),
+    // Kept as the normalized decimal string to avoid losing precision
+    Decimal(String),
+    Boolean(bool),
+    Date(Date),
+    Timestamp(DateTime),
+    Text(String),
+}
+
+impl Value {
+    pub fn is_null(&self) -> bool {
+        *self == Value::Null
+    }
+
+    pub fn is_numeric(&self) -> bool {
+        matches!(
+            self,
+            Value::Integer(_) | Value::Float(_) | Value::Decimal(_)
+        )
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Integer(value) => Some(*value as f64),
+            Value::Float(value) => Some(*value),
+            Value::Decimal(value) => value.parse().ok(),
+            Value::Boolean(value) => Some(if *value { 1.0 } else { 0.0 }),
+            _ => None,
+        }
+    }
+
+    // Sort order used by in-memory views: NULL first, numbers by value, the rest as text
+    pub fn compare(&self, other: &Value) -> Ordering {
+        match (self, other) {
+            (Value::Null, Value::Null) => Ordering::Equal,
+            (Value::Null, _) => Ordering::Less,
+            (_, Value::Null) => Ordering::Greater,
+            (Value::Date(a), Value::Date(b)) => a.cmp(b),
+            (Value::Timestamp(a), Value::Timestamp(b)) => a.cmp(b),
+            // Like the default MySQL collations
+            (Value::Text(a), Value::Text(b)) => a.to_lowercase().cmp(&b.to_lowercase()),
+            _ => match (self.as_f64(), other.as_f64()) {
+                (Some(a), Some(b)) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+                _ => self.to_string().cmp(&other.to_string()),
+            },
+        }
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        match self {
+            Value::Null => JsonValue::Null,
+            Value::Integer(value) => json!(value),
+            Value::Float(value) => json!(value),
+            Value::Boolean(value) => json!(value),
+            other => json!(other.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Null => Ok(()),
+            Value::Integer(value) => write!(f, "{}", value),
+            Value::Float(value) => write!(f, "{}", value),
+            Value::Decimal(value) => write!(f, "{}", value),
+            Value::Boolean(value) => write!(f, "{}", value),
+            Value::Date(value) => write!(f, "{}", value),
+            Value::Timestamp(value) => write!(f, "{}", value),
+            Value::Text(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+impl From<&Value> for mysql::Value {
+    fn from(value: &Value) -> Self {
+        match value {
+            Value::Null => mysql::Value::NULL,
+            Value::Integer(value) => mysql::Value::from(*value),
+            Value::Float(value) => mysql::Value::from(*value),
+            Value::Boolean(value) => mysql::Value::from(*value),
+            other => mysql::Value::from(other.to_string()),
+        }
+    }
+}
+
+fn parse_decimal(input: &str) -> Option<String> {
+    let (sign, digits) = match input.strip_prefix('-') {
+        Some(rest) => ("-", rest),
+        None => ("", input.strip_prefix('+').unwrap_or(input)),
+    };
+    let mut parts = digits.splitn(2, '.');
+    let whole = parts.next().unwrap_or_default();
+    let fraction = parts.next();
+
+    let all_digits = |part: &str| part.chars().all(|c| c.is_ascii_digit());
+    let fraction_digits = fraction.unwrap_or_default();
+    if !all_digits(whole) || !all_digits(fraction_digits) {
+        return None;
+    }
+    if whole.is_empty() && fraction_digits.is_empty() {
+        return None;
+    }
+
+    let whole = whole.trim_start_matches('0');
+    let whole = if whole.is_empty() { "0" } else { whole };
+    Some(match fraction {
+        Some(fraction) if !fraction.is_empty() => format!("{}{}.{}", sign, whole, fraction),
+        _ => format!("{}{}", sign, whole),
+    })
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Date {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+}
+
+impl Date {
+    // Accepts YYYY-MM-DD
+    pub fn parse(input: &str) -> Option<Date> {
+        let mut parts = input.split('-');
+        let year: u16 = parts.next()?.parse().ok()?;
+        let month: u8 = parts.next()?.parse().ok()?;
+        let day: u8 = parts.next()?.parse().ok()?;
+        if parts.next().is_some() || !(1..=12).contains(&month) {
+            return None;
+        }
+        if day < 1 || day > days_in_month(year, month) {
+            return None;
+        }
+        Some(Date { year, month, day })
+    }
+
+    // Days since 1970-01-01
+    pub fn days_since_epoch(&self) -> i64 {
+        let (month, day) = (i64::from(self.month), i64::from(self.day));
+        let year = i64::from(self.year) - if month <= 2 { 1 } else { 0 };
+        let era = year.div_euclid(400);
+        let year_of_era = year - era * 400;
+        let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        era * 146_097 + day_of_era - 719_468
+    }
+
+    // Inverse of `days_since_epoch`, None outside of years 0 to 65535
+    pub fn from_days(days: i64) -> Option<Date> {
+        let days = days + 719_468;
+        let era = days.div_euclid(146_097);
+        let day_of_era = days - era * 146_097;
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let shifted_month = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+        let month = if shifted_month < 10 {
+            shifted_month + 3
+        } else {
+            shifted_month - 9
+        };
+        let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+        if !(0..=i64::from(u16::MAX)).contains(&year) {
+            return None;
+        }
+        Some(Date {
+            year: year as u16,
+            month: month as u8,
+            day: day as u8,
+        })
+    }
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+pub fn days_in_month(year: u16, month: u8) -> u8 {
+    match month {
+        4 | 6 | 9 | 11 => 30,
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        _ => 31,
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DateTime {
+    pub date: Date,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl DateTime {
+    // Accepts YYYY-MM-DD, YYYY-MM-DD HH:MM and YYYY-MM-DD HH:MM:SS (also with a `T` separator)
+    pub fn parse(input: &str) -> Option<DateTime> {
+        let mut parts = input.splitn(2, [' ', 'T']);
+        let date = Date::parse(parts.next()?)?;
+        let time = match parts.next() {
+            Some(time) => time.trim(),
+            None => {
+                return Some(DateTime {
+                    date,
+                    hour: 0,
+                    minute: 0,
+                    second: 0,
+                })
+            }
+        };
+        // Fractional seconds are dropped
+        let time = time.split('.').next()?;
+        let mut parts = time.split(':');
+        let hour: u8 = parts.next()?.parse().ok()?;
+        let minute: u8 = parts.next()?.parse().ok()?;
+        let second: u8 = match parts.next() {
+            Some(second) => second.parse().ok()?,
+            None => 0,
+        };
+        if parts.next().is_some() || hour > 23 || minute > 59 || second > 59 {
+            return None;
+        }
+        Some(DateTime {
+            date,
+            hour,
+            minute,
+            second,
+        })
+    }
+}
+
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {:02}:{:02}:{:02}",
+            self.date, self.hour, self.minute, self.second
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::value::{ColumnType, Date, DateTime, Value};
+
+    #[test]
+    fn test_parse_values() {
+        assert_eq!(
+            ColumnType::Integer.parse("-42").unwrap(),
+            Value::Integer(-42)
+        );
+        assert_eq!(ColumnType::Integer.parse("").unwrap(), Value::Null);
+        assert!(ColumnType::Integer.parse("4.2").is_err());
+
+        assert_eq!(ColumnType::Float.parse("0.5").unwrap(), Value::Float(0.5));
+        assert!(ColumnType::Float.parse("NaN").is_err());
+
+        assert_eq!(
+            ColumnType::Decimal.parse("007.50").unwrap(),
+            Value::Decimal("7.50".to_owned())
+        );
+        assert!(ColumnType::Decimal.parse("1e3").is_err());
+
+        assert_eq!(
+            ColumnType::Boolean.parse("Yes").unwrap(),
+            Value::Boolean(true)
+        );
+        assert!(ColumnType::Boolean.parse("maybe").is_err());
+
+        assert_eq!(ColumnType::Text.parse("").unwrap(), Value::Null);
+        assert_eq!(
+            ColumnType::Text.parse(" x ").unwrap(),
+            Value::Text(" x ".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_parse_dates() {
+        assert_eq!(
+            Date::parse("2020-02-29"),
+            Some(Date {
+                year: 2020,
+                month: 2,
+                day: 29
+            })
+        );
+        assert_eq!(Date::parse("2019-02-29"), None);
+        assert_eq!(Date::parse("2019-13-01"), None);
+
+        for input in &["1970-01-01", "2000-02-29", "2020-10-01", "1899-12-30"] {
+            let date = Date::parse(input).unwrap();
+            assert_eq!(Date::from_days(date.days_since_epoch()), Some(date));
+        }
+        assert_eq!(Date::parse("2020-10-01").unwrap().days_since_epoch(), 18536);
+
+        let timestamp = DateTime::parse("2020-10-01T12:30").unwrap();
+        assert_eq!(timestamp.to_string(), "2020-10-01 12:30:00");
+        assert_eq!(DateTime::parse("2020-10-01 24:00:00"), None);
+    }
+
+    #[test]
+    fn test_sql_type_mapping() {
+        assert_eq!(ColumnType::from_sql_type("int(11)"), ColumnType::Integer);
+        assert_eq!(ColumnType::from_sql_type("tinyint(1)"), ColumnType::Boolean);
+        assert_eq!(
+            ColumnType::from_sql_type("decimal(10,2)"),
+            ColumnType::Decimal
+        );
+        assert_eq!(ColumnType::from_sql_type("double"), ColumnType::Float);
+        assert_eq!(ColumnType::from_sql_type("date"), ColumnType::Date);
+        assert_eq!(ColumnType::from_sql_type("datetime"), ColumnType::Timestamp);
+        assert_eq!(ColumnType::from_sql_type("varchar(255)"), ColumnType::Text);
+    }
+}
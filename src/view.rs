@@ -0,0 +1,487 @@
+use std::cmp::Ordering;
+
+use mysql::serde_json::{json, Value as JsonValue};
+
+use crate::database::{quote_identifier, Column, Row};
+use crate::draw_table::parse_column_label;
+use crate::error::Error;
+use crate::value::Value;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Direction {
+    Ascending,
+    Descending,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SortKey {
+    pub column: String,
+    pub direction: Direction,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Operator {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    Contains,
+    NotContains,
+}
+
+// Longer symbols first, so `<=` isn't read as `<`
+const OPERATORS: [(&str, Operator); 8] = [
+    ("!=", Operator::NotEqual),
+    ("<=", Operator::LessOrEqual),
+    (">=", Operator::GreaterOrEqual),
+    ("!~", Operator::NotContains),
+    ("=", Operator::Equal),
+    ("<", Operator::Less),
+    (">", Operator::Greater),
+    ("~", Operator::Contains),
+];
+
+impl Operator {
+    pub fn symbol(&self) -> &'static str {
+        OPERATORS
+            .iter()
+            .find(|(_, operator)| operator == self)
+            .map(|(symbol, _)| *symbol)
+            .unwrap_or_default()
+    }
+
+    fn from_symbol(symbol: &str) -> Option<Operator> {
+        OPERATORS
+            .iter()
+            .find(|(known, _)| *known == symbol)
+            .map(|(_, operator)| *operator)
+    }
+
+    // The operator the text starts with and the rest of it
+    pub fn split_prefix(text: &str) -> Option<(Operator, &str)> {
+        OPERATORS
+            .iter()
+            .find(|(symbol, _)| text.starts_with(symbol))
+            .map(|(symbol, operator)| (*operator, &text[symbol.len()..]))
+    }
+
+    // `operand` is text for (not) contains, which ignores case
+    pub fn matches(&self, value: &Value, operand: &Value) -> bool {
+        let contains = || {
+            let needle = operand.to_string().to_lowercase();
+            !value.is_null() && value.to_string().to_lowercase().contains(&needle)
+        };
+        match self {
+            Operator::Contains => contains(),
+            Operator::NotContains => !value.is_null() && !contains(),
+            Operator::Equal if operand.is_null() => value.is_null(),
+            Operator::NotEqual if operand.is_null() => !value.is_null(),
+            // Like SQL, comparing with NULL is never true
+            _ if value.is_null() => false,
+            operator => {
+                let ordering = value.compare(operand);
+                match operator {
+                    Operator::Equal => ordering == Ordering::Equal,
+                    Operator::NotEqual => ordering != Ordering::Equal,
+                    Operator::Less => ordering == Ordering::Less,
+                    Operator::LessOrEqual => ordering != Ordering::Greater,
+                    Operator::Greater => ordering == Ordering::Greater,
+                    Operator::GreaterOrEqual => ordering != Ordering::Less,
+                    Operator::Contains | Operator::NotContains => unreachable!(),
+                }
+            }
+        }
+    }
+}
+
+// `value` is parsed with the column type, except for (not) contains which matches text
+#[derive(Debug, Clone, PartialEq)]
+pub struct Filter {
+    pub column: String,
+    pub operator: Operator,
+    pub value: Value,
+}
+
+impl Filter {
+    pub fn matches(&self, value: &Value) -> bool {
+        self.operator.matches(value, &self.value)
+    }
+}
+
+// Sorting and filtering of the sheet. Applied by MySQL for the sender and
+// in memory on receivers, which get their data over UDP.
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct View {
+    pub sort: Vec<SortKey>,
+    pub filters: Vec<Filter>,
+    // Receivers adopt a shared view instead of their own
+    pub shared: bool,
+}
+
+// Finds a column by its name or its header letter
+pub fn find_column(columns: &[Column], label: &str) -> Option<usize> {
+    columns
+        .iter()
+        .position(|column| column.name == label)
+        .or_else(|| parse_column_label(label).filter(|index| *index < columns.len()))
+}
+
+impl View {
+    pub fn is_empty(&self) -> bool {
+        self.sort.is_empty() && self.filters.is_empty()
+    }
+
+    // A plain click sorts by the column alone, cycling ascending, descending and unsorted.
+    // With `add` the column is added to (or cycled within) the existing sort keys.
+    pub fn toggle_sort(&mut self, column: &str, add: bool) {
+        let position = self.sort.iter().position(|key| key.column == column);
+        if !add && (self.sort.len() > 1 || position.is_none()) {
+            self.sort.clear();
+        }
+        match self.sort.iter().position(|key| key.column == column) {
+            Some(index) if self.sort[index].direction == Direction::Ascending => {
+                self.sort[index].direction = Direction::Descending
+            }
+            Some(index) => {
+                self.sort.remove(index);
+            }
+            None => self.sort.push(SortKey {
+                column: column.to_owned(),
+                direction: Direction::Ascending,
+            }),
+        }
+    }
+
+    // Arrow and priority shown in the column header
+    pub fn sort_label(&self, column: &str) -> String {
+        match self.sort.iter().position(|key| key.column == column) {
+            Some(index) => {
+                let arrow = match self.sort[index].direction {
+                    Direction::Ascending => "▲",
+                    Direction::Descending => "▼",
+                };
+                if self.sort.len() > 1 {
+                    format!(" {}{}", arrow, index + 1)
+                } else {
+                    format!(" {}", arrow)
+                }
+            }
+            None => String::new(),
+        }
+    }
+
+    // Parses predicates like `number > 5; B ~ text; day = null` separated by `;`.
+    // Columns are referenced by name or header letter.
+    pub fn parse_filters(input: &str, columns: &[Column]) -> Result<Vec<Filter>, Error> {
+        input
+            .split(';')
+            .map(str::trim)
+            .filter(|predicate| !predicate.is_empty())
+            .map(|predicate| {
+                let invalid = || Error::new(format!("Invalid filter \"{}\"", predicate).as_str());
+
+                let (position, symbol) = OPERATORS
+                    .iter()
+                    .filter_map(|(symbol, _)| {
+                        predicate.find(symbol).map(|position| (position, *symbol))
+                    })
+                    .min_by_key(|(position, symbol)| (*position, -(symbol.len() as i32)))
+                    .ok_or_else(invalid)?;
+                let operator = Operator::from_symbol(symbol).ok_or_else(invalid)?;
+
+                let label = predicate[..position].trim();
+                let raw_value = predicate[position + symbol.len()..].trim();
+                let raw_value = raw_value
+                    .strip_prefix('"')
+                    .and_then(|value| value.strip_suffix('"'))
+                    .unwrap_or(raw_value);
+
+                let index = find_column(columns, label)
+                    .ok_or_else(|| Error::new(format!("Unknown column \"{}\"", label).as_str()))?;
+                let column = &columns[index];
+                let value = match operator {
+                    Operator::Contains | Operator::NotContains => Value::Text(raw_value.to_owned()),
+                    _ if raw_value.eq_ignore_ascii_case("null") => Value::Null,
+                    _ => column.kind.parse(raw_value)?,
+                };
+                Ok(Filter {
+                    column: column.name.clone(),
+                    operator,
+                    value,
+                })
+            })
+            .collect()
+    }
+
+    // Parses sort keys like `number, -B`, a leading `-` sorts descending
+    pub fn parse_sort(input: &str, columns: &[Column]) -> Result<Vec<SortKey>, Error> {
+        input
+            .split(',')
+            .map(str::trim)
+            .filter(|key| !key.is_empty())
+            .map(|key| {
+                let (label, direction) = match key.strip_prefix('-') {
+                    Some(label) => (label.trim(), Direction::Descending),
+                    None => (key, Direction::Ascending),
+                };
+                let index = find_column(columns, label)
+                    .ok_or_else(|| Error::new(format!("Unknown column \"{}\"", label).as_str()))?;
+                Ok(SortKey {
+                    column: columns[index].name.clone(),
+                    direction,
+                })
+            })
+            .collect()
+    }
+
+    // Text for the filter bar, the inverse of `parse_filters`
+    pub fn filter_text(&self) -> String {
+        self.filters
+            .iter()
+            .map(|filter| {
+                let value = if filter.value.is_null() {
+                    "null".to_owned()
+                } else {
+                    filter.value.to_string()
+                };
+                format!("{} {} {}", filter.column, filter.operator.symbol(), value)
+            })
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+
+    // WHERE conditions joined with AND and their parameters, filters on unknown columns are skipped
+    pub fn where_sql(&self, columns: &[Column]) -> (Vec<String>, Vec<mysql::Value>) {
+        let mut conditions = Vec::new();
+        let mut params = Vec::new();
+        for filter in self.filters.iter() {
+            if !columns.iter().any(|column| column.name == filter.column) {
+                continue;
+            }
+            let name = quote_identifier(&filter.column);
+            match filter.operator {
+                Operator::Contains | Operator::NotContains => {
+                    let not = if filter.operator == Operator::NotContains {
+                        "NOT "
+                    } else {
+                        ""
+                    };
+                    conditions.push(format!("{} {}LIKE ?", name, not));
+                    let escaped = filter
+                        .value
+                        .to_string()
+                        .replace('\\', "\\\\")
+                        .replace('%', "\\%")
+                        .replace('_', "\\_");
+                    params.push(mysql::Value::from(format!("%{}%", escaped)));
+                }
+                Operator::Equal if filter.value.is_null() => {
+                    conditions.push(format!("{} IS NULL", name))
+                }
+                Operator::NotEqual if filter.value.is_null() => {
+                    conditions.push(format!("{} IS NOT NULL", name))
+                }
+                operator => {
+                    conditions.push(format!("{} {} ?", name, operator.symbol()));
+                    params.push(mysql::Value::from(&filter.value));
+                }
+            }
+        }
+        (conditions, params)
+    }
+
+    // ORDER BY list, the id keeps the order stable
+    pub fn order_sql(&self, columns: &[Column]) -> String {
+        let mut keys: Vec<String> = self
+            .sort
+            .iter()
+            .filter(|key| columns.iter().any(|column| column.name == key.column))
+            .map(|key| {
+                let direction = match key.direction {
+                    Direction::Ascending => "ASC",
+                    Direction::Descending => "DESC",
+                };
+                format!("{} {}", quote_identifier(&key.column), direction)
+            })
+            .collect();
+        keys.push("id".to_owned());
+        keys.join(", ")
+    }
+
+    // Filters and sorts rows in memory, with the same semantics as the SQL version
+    pub fn apply(&self, columns: &[Column], rows: &mut Vec<Row>) {
+        let position = |name: &str| columns.iter().position(|column| column.name == name);
+        let value = |row: &Row, index: usize| row.values.get(index).cloned().unwrap_or_default();
+
+        for filter in self.filters.iter() {
+            if let Some(index) = position(&filter.column) {
+                rows.retain(|row| filter.matches(&value(row, index)));
+            }
+        }
+
+        let keys: Vec<(usize, Direction)> = self
+            .sort
+            .iter()
+            .filter_map(|key| position(&key.column).map(|index| (index, key.direction)))
+            .collect();
+        rows.sort_by(|a, b| {
+            keys.iter()
+                .map(|(index, direction)| {
+                    let ordering = value(a, *index).compare(&value(b, *index));
+                    match direction {
+                        Direction::Ascending => ordering,
+                        Direction::Descending => ordering.reverse(),
+                    }
+                })
+                .find(|ordering| *ordering != Ordering::Equal)
+                .unwrap_or_else(|| a.id.cmp(&b.id))
+        });
+    }
+
+    pub fn to_json(&self) -> JsonValue {
+        json!({
+            "sort": self.sort.iter().map(|key| json!({
+                "column": key.column,
+                "descending": key.direction == Direction::Descending,
+            })).collect::<Vec<_>>(),
+            "filters": self.filters.iter().map(|filter| json!({
+                "column": filter.column,
+                "operator": filter.operator.symbol(),
+                "value": filter.value.to_json(),
+            })).collect::<Vec<_>>(),
+        })
+    }
+
+    // Filter values are decoded with the types of `columns`, unknown columns are dropped
+    pub fn from_json(json: &JsonValue, columns: &[Column]) -> View {
+        let sort = json["sort"]
+            .as_array()
+            .map(|keys| {
+                keys.iter()
+                    .filter_map(|key| {
+                        Some(SortKey {
+                            column: key["column"].as_str()?.to_owned(),
+                            direction: if key["descending"].as_bool()? {
+                                Direction::Descending
+                            } else {
+                                Direction::Ascending
+                            },
+                        })
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        let filters = json["filters"]
+            .as_array()
+            .map(|filters| {
+                filters
+                    .iter()
+                    .filter_map(|filter| {
+                        let name = filter["column"].as_str()?;
+                        let column = columns.iter().find(|column| column.name == name)?;
+                        let operator = Operator::from_symbol(filter["operator"].as_str()?)?;
+                        let value = match operator {
+                            Operator::Contains | Operator::NotContains => {
+                                Value::Text(filter["value"].as_str()?.to_owned())
+                            }
+                            _ => column.kind.decode_json(&filter["value"]).ok()?,
+                        };
+                        Some(Filter {
+                            column: name.to_owned(),
+                            operator,
+                            value,
+                        })
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        View {
+            sort,
+            filters,
+            shared: true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::{example_columns, example_rows};
+    use crate::value::Value;
+    use crate::view::{Direction, Operator, View};
+
+    #[test]
+    fn test_parse_filters() {
+        let columns = example_columns();
+        let filters = View::parse_filters("number >= 5; B ~ text; day != null", &columns).unwrap();
+        assert_eq!(filters.len(), 3);
+        assert_eq!(filters[0].operator, Operator::GreaterOrEqual);
+        assert_eq!(filters[0].value, Value::Integer(5));
+        assert_eq!(filters[1].column, "text");
+        assert_eq!(filters[1].operator, Operator::Contains);
+        assert_eq!(filters[2].value, Value::Null);
+
+        assert!(View::parse_filters("number > five", &columns).is_err());
+        assert!(View::parse_filters("missing = 1", &columns).is_err());
+
+        let sort = View::parse_sort("number, -B", &columns).unwrap();
+        assert_eq!(sort[0].direction, Direction::Ascending);
+        assert_eq!(sort[1].column, "text");
+        assert_eq!(sort[1].direction, Direction::Descending);
+        assert!(View::parse_sort("-missing", &columns).is_err());
+    }
+
+    #[test]
+    fn test_toggle_sort() {
+        let mut view = View::default();
+        view.toggle_sort("number", false);
+        assert_eq!(view.sort[0].direction, Direction::Ascending);
+        view.toggle_sort("number", false);
+        assert_eq!(view.sort[0].direction, Direction::Descending);
+        view.toggle_sort("text", true);
+        assert_eq!(view.sort.len(), 2);
+        assert_eq!(view.sort_label("text"), " ▲2");
+        // A plain click replaces a multi-key sort
+        view.toggle_sort("text", false);
+        assert_eq!(view.sort.len(), 1);
+        assert_eq!(view.sort[0].column, "text");
+        view.toggle_sort("text", false);
+        view.toggle_sort("text", false);
+        assert!(view.sort.is_empty());
+    }
+
+    #[test]
+    fn test_apply_in_memory() {
+        let columns = example_columns();
+        let mut rows = example_rows();
+
+        let mut view = View::default();
+        view.toggle_sort("number", false);
+        view.toggle_sort("number", false);
+        view.apply(&columns, &mut rows);
+        let ids: Vec<i32> = rows.iter().map(|row| row.id).collect();
+        assert_eq!(ids, vec![2, 1, 3]);
+
+        view.filters = View::parse_filters("text != null; price < 0", &columns).unwrap();
+        view.apply(&columns, &mut rows);
+        let ids: Vec<i32> = rows.iter().map(|row| row.id).collect();
+        assert_eq!(ids, vec![2]);
+
+        let json = view.to_json();
+        assert_eq!(View::from_json(&json, &columns).filters, view.filters);
+
+        // Text is compared ignoring case, like MySQL does
+        let mut rows = example_rows();
+        rows[0].values[1] = Value::Text("Test".to_owned());
+        let mut view = View::default();
+        view.toggle_sort("text", false);
+        view.apply(&columns, &mut rows);
+        let ids: Vec<i32> = rows.iter().map(|row| row.id).collect();
+        assert_eq!(ids, vec![3, 2, 1]);
+        view.filters = View::parse_filters("text = TEST", &columns).unwrap();
+        view.apply(&columns, &mut rows);
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].id, 1);
+    }
+}
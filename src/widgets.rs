@@ -2,21 +2,26 @@ use std::cell::RefCell;
 use std::rc::Rc;
 
 use fltk::app;
-use fltk::dialog::alert;
-use fltk::input::Input;
+use fltk::app::Sender;
+use fltk::browser::HoldBrowser;
+use fltk::button::{Button, CheckButton};
+use fltk::dialog::{alert, choice, input};
+use fltk::frame::Frame;
+use fltk::input::{Input, MultilineInput};
+use fltk::menu::MenuItem;
 use fltk::table::Table;
 use fltk::window::DoubleWindow;
-use fltk::{Event, GroupExt, InputExt, Key, TableExt, WidgetExt, WindowExt};
+use fltk::{BrowserExt, Event, GroupExt, InputExt, Key, TableExt, WidgetExt, WindowExt};
 
 use crate::connector::{Connector, MyConnectorResult};
+use crate::search::SearchOptions;
+use crate::Message;
 
 pub fn make_window(x: i32, y: i32, w: i32, h: i32, title: &str) -> DoubleWindow {
     let mut window = DoubleWindow::new(x, y, w, h, title).center_screen();
     window.set_callback(Box::new(|| {
-        let event = app::event();
-        let close = event == Event::Close;
-        let keyboard_close = event == Event::Shortcut && app::event_key() == Key::Escape;
-        if close || keyboard_close {
+        // Escape cancels editing in the table and doesn't close the window
+        if app::event() == Event::Close {
             // let x = fltk::dialog::choice(0, 0, "Would you like to save your work?", "No", "Cancel", "Yes");
             app::quit();
         }
@@ -120,3 +125,178 @@ pub fn make_input(
     }));
     input
 }
+
+pub fn confirm(message: &str, action: &str) -> bool {
+    choice(0, 0, message, "Cancel", action, "") == 1
+}
+
+// Returns None when the dialog is cancelled or the text is empty
+pub fn ask_text(message: &str, default: &str) -> Option<String> {
+    input(0, 0, message, default)
+        .map(|text| text.trim().to_owned())
+        .filter(|text| !text.is_empty())
+}
+
+// Pops up a menu at the mouse position and returns the index of the chosen item
+pub fn pick(items: &[&str]) -> Option<usize> {
+    pick_at(app::event_x(), app::event_y(), items)
+}
+
+pub fn pick_at(x: i32, y: i32, items: &[&str]) -> Option<usize> {
+    let mut menu = MenuItem::new(items);
+    let item = menu.popup(x, y)?;
+    items
+        .iter()
+        .position(|label| item.label().as_deref() == Some(*label))
+}
+
+// Modal list of history entries, returns the index of the one to restore
+pub fn choose_history(title: &str, lines: &[String]) -> Option<usize> {
+    let mut window = DoubleWindow::new(0, 0, 500, 300, title).center_screen();
+    window.make_modal(true);
+
+    let mut browser = HoldBrowser::new(5, 5, 490, 255, "");
+    for line in lines {
+        browser.add(line);
+    }
+    let mut restore = Button::new(290, 265, 100, 30, "Restore");
+    let mut close = Button::new(395, 265, 100, 30, "Close");
+    window.end();
+
+    let chosen = Rc::from(RefCell::from(None));
+    let chosen_clone = chosen.clone();
+    let mut window_clone = window.clone();
+    restore.set_callback(Box::new(move || {
+        // Browser lines are numbered from 1
+        let line = (1..=browser.size()).find(|line| browser.selected(*line));
+        if let Some(line) = line {
+            *chosen_clone.borrow_mut() = Some(line as usize - 1);
+            window_clone.hide();
+        }
+    }));
+    let mut window_clone = window.clone();
+    close.set_callback(Box::new(move || window_clone.hide()));
+
+    window.show();
+    while window.shown() {
+        app::wait().unwrap();
+    }
+    let chosen = *chosen.borrow();
+    chosen
+}
+
+// fltk only copies from and pastes into inputs, so the clipboard goes through a hidden
+// one. Pasted text arrives later as a paste event and is announced with `Message::Paste`.
+#[derive(Clone)]
+pub struct Clipboard {
+    input: MultilineInput,
+    pasted: Rc<RefCell<String>>,
+}
+
+impl Clipboard {
+    pub fn new(sender: Sender<Message>) -> Clipboard {
+        let mut input = MultilineInput::new(0, 0, 0, 0, "");
+        input.hide();
+        // The default of 32 767 characters would cut big ranges
+        input.set_maximum_size(u32::MAX / 2);
+        let pasted = Rc::from(RefCell::from(String::new()));
+        let pasted_clone = pasted.clone();
+        let input_clone = input.clone();
+        input.handle(Box::new(move |event| match event {
+            Event::Paste => {
+                *pasted_clone.borrow_mut() = app::event_text();
+                input_clone.set_value("");
+                sender.send(Message::Paste);
+                true
+            }
+            _ => false,
+        }));
+        Clipboard { input, pasted }
+    }
+
+    pub fn copy(&mut self, text: &str) {
+        self.input.set_value(text);
+        self.input.set_position(0).unwrap_or(());
+        self.input.set_mark(text.len() as u32).unwrap_or(());
+        self.input.copy().unwrap_or(());
+        self.input.set_value("");
+    }
+
+    pub fn request_paste(&self) {
+        app::paste(&self.input);
+    }
+
+    // Text of the last paste event
+    pub fn take_pasted(&self) -> String {
+        std::mem::take(&mut *self.pasted.borrow_mut())
+    }
+
+    pub fn widget(&self) -> &MultilineInput {
+        &self.input
+    }
+}
+
+// Find and replace window, it stays open next to the sheet and its buttons send messages
+pub struct FindBar {
+    pub window: DoubleWindow,
+    pub find: Input,
+    pub replace: Input,
+    pub status: Frame,
+    case_sensitive: CheckButton,
+    whole_cell: CheckButton,
+    regex: CheckButton,
+}
+
+impl FindBar {
+    pub fn new(sender: Sender<Message>) -> FindBar {
+        let mut window = DoubleWindow::new(0, 0, 400, 130, "Find and replace").center_screen();
+        let mut find = Input::new(70, 5, 325, 25, "Find:");
+        let replace = Input::new(70, 35, 325, 25, "Replace:");
+        let case_sensitive = CheckButton::new(5, 65, 105, 25, "Match case");
+        let whole_cell = CheckButton::new(110, 65, 100, 25, "Whole cell");
+        let regex = CheckButton::new(210, 65, 70, 25, "Regex");
+        let status = Frame::new(280, 65, 115, 25, "");
+        let mut previous = Button::new(5, 100, 75, 25, "Previous");
+        let mut next = Button::new(85, 100, 75, 25, "Next");
+        let mut replace_one = Button::new(165, 100, 110, 25, "Replace");
+        let mut replace_all = Button::new(280, 100, 115, 25, "Replace all");
+        window.end();
+
+        previous.set_callback(Box::new(move || sender.send(Message::Find(false))));
+        next.set_callback(Box::new(move || sender.send(Message::Find(true))));
+        replace_one.set_callback(Box::new(move || sender.send(Message::Replace(false))));
+        replace_all.set_callback(Box::new(move || sender.send(Message::Replace(true))));
+        // Enter finds the next match
+        find.handle(Box::new(move |event| {
+            let enter = event == Event::KeyDown && app::event_key() == Key::Enter;
+            if enter {
+                sender.send(Message::Find(true));
+            }
+            enter
+        }));
+        // Closing (or Escape) clears the highlighted matches
+        let mut window_clone = window.clone();
+        window.set_callback(Box::new(move || {
+            window_clone.hide();
+            sender.send(Message::CloseFind);
+        }));
+
+        FindBar {
+            window,
+            find,
+            replace,
+            status,
+            case_sensitive,
+            whole_cell,
+            regex,
+        }
+    }
+
+    pub fn options(&self) -> SearchOptions {
+        SearchOptions {
+            case_sensitive: self.case_sensitive.is_checked(),
+            whole_cell: self.whole_cell.is_checked(),
+            regex: self.regex.is_checked(),
+        }
+    }
+}
@@ -0,0 +1,879 @@
+use std::collections::HashMap;
+use std::io::{Read, Seek, Write};
+
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::csv::Record;
+use crate::database::{Column, Row};
+use crate::draw_table::{column_label, parse_column_label};
+use crate::error::Error;
+use crate::value::{ColumnType, Date, DateTime, Value};
+
+// Width columns get when a file doesn't set one, same as the table default
+pub const DEFAULT_WIDTH: u32 = 170;
+// Guards against files declaring widths for every possible column
+const MAX_COLUMNS: usize = 1024;
+// Excel counts column widths in characters of about 7 pixels
+const PIXELS_PER_CHARACTER: f64 = 7.0;
+const PIXELS_PER_INCH: f64 = 96.0;
+// Excel serial numbers count days from 1899-12-30
+const EXCEL_EPOCH_DAYS: i64 = 25569;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Format {
+    Xlsx,
+    Ods,
+}
+
+impl Format {
+    pub fn from_path(path: &str) -> Option<Format> {
+        let path = path.to_lowercase();
+        if path.ends_with(".xlsx") {
+            Some(Format::Xlsx)
+        } else if path.ends_with(".ods") {
+            Some(Format::Ods)
+        } else {
+            None
+        }
+    }
+}
+
+// The first sheet of a file: cell values as text, like records read from CSV, and the
+// column widths in pixels. Dates and booleans are written the way `ColumnType::parse` reads them.
+#[derive(Debug, Default, PartialEq)]
+pub struct Workbook {
+    pub records: Vec<Record>,
+    pub widths: Vec<Option<u32>>,
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            // Not allowed in XML 1.0
+            c if c < ' ' && c != '\n' && c != '\t' && c != '\r' => (),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn attribute(element: &BytesStart, name: &[u8]) -> Result<Option<String>, Error> {
+    for attribute in element.attributes() {
+        let attribute = attribute?;
+        if attribute.key == name {
+            let value = attribute.unescaped_value()?;
+            return Ok(Some(String::from_utf8_lossy(&value).into_owned()));
+        }
+    }
+    Ok(None)
+}
+
+fn read_entry<R: Read + Seek>(archive: &mut ZipArchive<R>, name: &str) -> Result<String, Error> {
+    let mut text = String::new();
+    archive.by_name(name)?.read_to_string(&mut text)?;
+    Ok(text)
+}
+
+fn add_entry<W: Write + Seek>(zip: &mut ZipWriter<W>, name: &str, text: &str) -> Result<(), Error> {
+    zip.start_file(name, FileOptions::default())?;
+    zip.write_all(text.as_bytes())?;
+    Ok(())
+}
+
+// Sets the width of columns `first` to `last`, counted from 0
+fn set_widths(widths: &mut Vec<Option<u32>>, first: usize, last: usize, width: u32) {
+    let last = last.min(MAX_COLUMNS - 1);
+    if first > last {
+        return;
+    }
+    if widths.len() <= last {
+        widths.resize(last + 1, None);
+    }
+    widths[first..=last]
+        .iter_mut()
+        .for_each(|known| *known = Some(width));
+}
+
+// Puts a cell into a record, filling skipped cells with empty text
+fn set_field(fields: &mut Vec<String>, col: usize, value: String) {
+    if col >= MAX_COLUMNS {
+        return;
+    }
+    if fields.len() <= col {
+        fields.resize(col + 1, String::new());
+    }
+    fields[col] = value;
+}
+
+impl Workbook {
+    pub fn read<R: Read + Seek>(reader: R, format: Format) -> Result<Workbook, Error> {
+        let mut archive = ZipArchive::new(reader)?;
+        match format {
+            Format::Xlsx => Workbook::read_xlsx(&mut archive),
+            Format::Ods => Workbook::read_ods(&read_entry(&mut archive, "content.xml")?),
+        }
+    }
+
+    // Column widths are in pixels, missing ones get the default
+    pub fn write<W: Write + Seek>(
+        writer: W,
+        format: Format,
+        columns: &[Column],
+        rows: &[Row],
+        widths: &[u32],
+    ) -> Result<W, Error> {
+        let width = |col: usize| widths.get(col).copied().unwrap_or(DEFAULT_WIDTH);
+        let widths: Vec<u32> = (0..columns.len()).map(width).collect();
+        let mut zip = ZipWriter::new(writer);
+        match format {
+            Format::Xlsx => write_xlsx(&mut zip, columns, rows, &widths)?,
+            Format::Ods => write_ods(&mut zip, columns, rows, &widths)?,
+        }
+        Ok(zip.finish()?)
+    }
+
+    fn read_xlsx<R: Read + Seek>(archive: &mut ZipArchive<R>) -> Result<Workbook, Error> {
+        let shared_strings = match read_entry(archive, "xl/sharedStrings.xml") {
+            Ok(xml) => read_shared_strings(&xml)?,
+            Err(_) => Vec::new(),
+        };
+        let date_styles = match read_entry(archive, "xl/styles.xml") {
+            Ok(xml) => read_date_styles(&xml)?,
+            Err(_) => Vec::new(),
+        };
+        let sheet = first_sheet_path(archive)?;
+        let xml = read_entry(archive, &sheet)?;
+
+        let mut workbook = Workbook::default();
+        let mut reader = Reader::from_str(&xml);
+        let mut buf = Vec::new();
+        let mut fields = Vec::new();
+        let mut line = 0;
+        // Reference, type and style of the open cell, and its value
+        let mut cell: Option<(usize, String, usize)> = None;
+        let mut value = String::new();
+        let mut in_value = false;
+        let mut next_col = 0;
+
+        loop {
+            let event = reader.read_event(&mut buf)?;
+            let empty = matches!(event, Event::Empty(_));
+            match event {
+                Event::Start(ref element) | Event::Empty(ref element) => {
+                    match element.name() {
+                        b"col" => {
+                            let number = |name| -> Result<Option<usize>, Error> {
+                                Ok(attribute(element, name)?.and_then(|value| value.parse().ok()))
+                            };
+                            let width = attribute(element, b"width")?
+                                .and_then(|width| width.parse::<f64>().ok());
+                            if let (Some(min), Some(max), Some(width)) =
+                                (number(b"min")?, number(b"max")?, width)
+                            {
+                                let pixels = (width * PIXELS_PER_CHARACTER).round() as u32;
+                                set_widths(
+                                    &mut workbook.widths,
+                                    min.saturating_sub(1),
+                                    max.saturating_sub(1),
+                                    pixels,
+                                );
+                            }
+                        }
+                        b"row" => {
+                            line = attribute(element, b"r")?
+                                .and_then(|r| r.parse().ok())
+                                .unwrap_or(line + 1);
+                            fields.clear();
+                            next_col = 0;
+                        }
+                        b"c" => {
+                            let col = attribute(element, b"r")?
+                                .and_then(|reference| {
+                                    // Letters of a reference like `AB12`
+                                    let letters =
+                                        reference.trim_end_matches(|c: char| c.is_ascii_digit());
+                                    parse_column_label(&letters.to_ascii_uppercase())
+                                })
+                                .unwrap_or(next_col);
+                            next_col = col + 1;
+                            let kind = attribute(element, b"t")?.unwrap_or_default();
+                            let style = attribute(element, b"s")?
+                                .and_then(|s| s.parse().ok())
+                                .unwrap_or(0);
+                            value.clear();
+                            // `<c/>` has no value and no end tag
+                            if !empty {
+                                cell = Some((col, kind, style));
+                            }
+                        }
+                        b"v" | b"t" => in_value = true,
+                        _ => (),
+                    }
+                }
+                Event::Text(text) if in_value => {
+                    value.push_str(&text.unescape_and_decode(&reader)?)
+                }
+                Event::End(ref element) => match element.name() {
+                    b"v" | b"t" => in_value = false,
+                    b"c" => {
+                        if let Some((col, kind, style)) = cell.take() {
+                            let text = match kind.as_str() {
+                                "s" => value
+                                    .trim()
+                                    .parse::<usize>()
+                                    .ok()
+                                    .and_then(|index| shared_strings.get(index))
+                                    .cloned()
+                                    .unwrap_or_default(),
+                                "b" => (value.trim() == "1").to_string(),
+                                "" | "n" => match date_styles.get(style) {
+                                    Some(Some(kind)) => serial_to_text(&value, *kind),
+                                    _ => value.clone(),
+                                },
+                                _ => value.clone(),
+                            };
+                            set_field(&mut fields, col, text);
+                        }
+                    }
+                    b"row" if fields.iter().any(|field| !field.is_empty()) => {
+                        workbook.records.push(Record {
+                            line,
+                            fields: std::mem::take(&mut fields),
+                        });
+                    }
+                    _ => (),
+                },
+                Event::Eof => break,
+                _ => (),
+            }
+            buf.clear();
+        }
+        Ok(workbook)
+    }
+
+    fn read_ods(xml: &str) -> Result<Workbook, Error> {
+        let mut workbook = Workbook::default();
+        let mut reader = Reader::from_str(xml);
+        let mut buf = Vec::new();
+        // Column widths by style name
+        let mut column_styles: HashMap<String, u32> = HashMap::new();
+        let mut style_name = String::new();
+        let mut tables = 0;
+        let mut fields = Vec::new();
+        let mut line = 0;
+        let mut rows_repeated = 1;
+        // Value and repetition of the open cell, None for empty cells
+        let mut cell: Option<(Option<String>, usize)> = None;
+        let mut text = String::new();
+        let mut in_text = false;
+        let mut col = 0;
+
+        loop {
+            let event = reader.read_event(&mut buf)?;
+            let (element, empty) = match &event {
+                Event::Start(element) => (Some(element), false),
+                Event::Empty(element) => (Some(element), true),
+                _ => (None, false),
+            };
+            if let Some(element) = element {
+                match element.name() {
+                    b"style:style" => {
+                        style_name = attribute(element, b"style:name")?.unwrap_or_default()
+                    }
+                    b"style:table-column-properties" => {
+                        let width = attribute(element, b"style:column-width")?
+                            .and_then(|width| length_to_pixels(&width));
+                        if let Some(width) = width {
+                            column_styles.insert(style_name.clone(), width);
+                        }
+                    }
+                    b"table:table" => tables += 1,
+                    // Only the first sheet is read
+                    _ if tables != 1 => (),
+                    b"table:table-column" => {
+                        let repeated = repeat_count(element, b"table:number-columns-repeated")?;
+                        let width = attribute(element, b"table:style-name")?
+                            .and_then(|name| column_styles.get(&name).copied());
+                        let first = col;
+                        col += repeated;
+                        if let Some(width) = width {
+                            set_widths(&mut workbook.widths, first, col - 1, width);
+                        }
+                    }
+                    b"table:table-row" => {
+                        col = 0;
+                        fields.clear();
+                        rows_repeated = repeat_count(element, b"table:number-rows-repeated")?;
+                        if empty {
+                            line += rows_repeated;
+                        }
+                    }
+                    b"table:table-cell" | b"table:covered-table-cell" => {
+                        let repeated = repeat_count(element, b"table:number-columns-repeated")?;
+                        let value = match attribute(element, b"office:value-type")?.as_deref() {
+                            Some("float") | Some("percentage") | Some("currency") => {
+                                attribute(element, b"office:value")?
+                            }
+                            Some("date") => attribute(element, b"office:date-value")?,
+                            Some("boolean") => attribute(element, b"office:boolean-value")?,
+                            _ => None,
+                        };
+                        text.clear();
+                        if empty {
+                            ods_cell(&mut fields, &mut col, value, &text, repeated);
+                        } else {
+                            cell = Some((value, repeated));
+                        }
+                    }
+                    b"text:p" if cell.is_some() => {
+                        if !text.is_empty() {
+                            text.push('\n');
+                        }
+                        in_text = !empty;
+                    }
+                    b"text:s" if in_text => {
+                        let count = repeat_count(element, b"text:c")?;
+                        text.push_str(&" ".repeat(count));
+                    }
+                    b"text:tab" if in_text => text.push('\t'),
+                    b"text:line-break" if in_text => text.push('\n'),
+                    _ => (),
+                }
+            }
+            match &event {
+                Event::Text(content) if in_text => {
+                    text.push_str(&content.unescape_and_decode(&reader)?)
+                }
+                Event::End(element) => match element.name() {
+                    b"text:p" => in_text = false,
+                    b"table:table-cell" | b"table:covered-table-cell" => {
+                        if let Some((value, repeated)) = cell.take() {
+                            ods_cell(&mut fields, &mut col, value, &text, repeated);
+                        }
+                    }
+                    b"table:table-row" if tables == 1 => {
+                        for _ in 0..rows_repeated {
+                            line += 1;
+                            if fields.iter().any(|field: &String| !field.is_empty()) {
+                                workbook.records.push(Record {
+                                    line,
+                                    fields: fields.clone(),
+                                });
+                            }
+                        }
+                    }
+                    _ => (),
+                },
+                Event::Eof => break,
+                _ => (),
+            }
+            buf.clear();
+        }
+        Ok(workbook)
+    }
+}
+
+// Typed value if the cell has one, its text otherwise
+fn ods_cell(
+    fields: &mut Vec<String>,
+    col: &mut usize,
+    value: Option<String>,
+    text: &str,
+    repeated: usize,
+) {
+    let value = value.unwrap_or_else(|| text.to_owned());
+    // Trailing empty cells are often repeated up to the last possible column
+    if !value.is_empty() {
+        for offset in 0..repeated.min(MAX_COLUMNS) {
+            set_field(fields, *col + offset, value.clone());
+        }
+    }
+    *col += repeated;
+}
+
+fn repeat_count(element: &BytesStart, name: &[u8]) -> Result<usize, Error> {
+    Ok(attribute(element, name)?
+        .and_then(|count| count.parse().ok())
+        .unwrap_or(1)
+        .max(1))
+}
+
+// Lengths like `1.5in`, `4cm`, `40mm` or `100pt`
+fn length_to_pixels(length: &str) -> Option<u32> {
+    let split = length.find(|c: char| c.is_ascii_alphabetic())?;
+    let number: f64 = length[..split].parse().ok()?;
+    let inches = match &length[split..] {
+        "in" => number,
+        "cm" => number / 2.54,
+        "mm" => number / 25.4,
+        "pt" => number / 72.0,
+        _ => return None,
+    };
+    Some((inches * PIXELS_PER_INCH).round() as u32)
+}
+
+fn read_shared_strings(xml: &str) -> Result<Vec<String>, Error> {
+    let mut strings = Vec::new();
+    let mut reader = Reader::from_str(xml);
+    let mut buf = Vec::new();
+    let mut in_text = false;
+    // Phonetic hints repeat the text in another script
+    let mut in_phonetic = false;
+
+    loop {
+        match reader.read_event(&mut buf)? {
+            Event::Start(ref element) => match element.name() {
+                b"si" => strings.push(String::new()),
+                b"t" => in_text = !in_phonetic,
+                b"rPh" => in_phonetic = true,
+                _ => (),
+            },
+            Event::Empty(ref element) if element.name() == b"si" => strings.push(String::new()),
+            Event::Text(text) if in_text => {
+                if let Some(string) = strings.last_mut() {
+                    string.push_str(&text.unescape_and_decode(&reader)?);
+                }
+            }
+            Event::End(ref element) => match element.name() {
+                b"t" => in_text = false,
+                b"rPh" => in_phonetic = false,
+                _ => (),
+            },
+            Event::Eof => break,
+            _ => (),
+        }
+        buf.clear();
+    }
+    Ok(strings)
+}
+
+// Date or timestamp formatting of a number format, None for plain numbers
+fn number_format_type(id: u32, code: Option<&str>) -> Option<ColumnType> {
+    match (id, code) {
+        (14..=17, _) => Some(ColumnType::Date),
+        (22, _) => Some(ColumnType::Timestamp),
+        (_, Some(code)) => {
+            // Quoted text and [colors] don't count
+            let mut plain = String::new();
+            let (mut quoted, mut bracketed) = (false, false);
+            for c in code.to_lowercase().chars() {
+                match c {
+                    '"' => quoted = !quoted,
+                    '[' if !quoted => bracketed = true,
+                    ']' if !quoted => bracketed = false,
+                    c if !quoted && !bracketed => plain.push(c),
+                    _ => (),
+                }
+            }
+            let date = plain.contains('y') || plain.contains('d');
+            let time = plain.contains('h') || plain.contains('s');
+            match (date, time) {
+                (true, true) => Some(ColumnType::Timestamp),
+                (true, false) => Some(ColumnType::Date),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+// Date type of every cell style, by style index
+fn read_date_styles(xml: &str) -> Result<Vec<Option<ColumnType>>, Error> {
+    let mut codes: HashMap<u32, String> = HashMap::new();
+    let mut formats = Vec::new();
+    let mut reader = Reader::from_str(xml);
+    let mut buf = Vec::new();
+    let mut in_cell_formats = false;
+
+    loop {
+        match reader.read_event(&mut buf)? {
+            Event::Start(ref element) | Event::Empty(ref element) => match element.name() {
+                b"numFmt" => {
+                    let id = attribute(element, b"numFmtId")?.and_then(|id| id.parse().ok());
+                    if let (Some(id), Some(code)) = (id, attribute(element, b"formatCode")?) {
+                        codes.insert(id, code);
+                    }
+                }
+                b"cellXfs" => in_cell_formats = true,
+                b"xf" if in_cell_formats => {
+                    let id = attribute(element, b"numFmtId")?
+                        .and_then(|id| id.parse().ok())
+                        .unwrap_or(0);
+                    formats.push(id);
+                }
+                _ => (),
+            },
+            Event::End(ref element) if element.name() == b"cellXfs" => in_cell_formats = false,
+            Event::Eof => break,
+            _ => (),
+        }
+        buf.clear();
+    }
+    Ok(formats
+        .into_iter()
+        .map(|id| number_format_type(id, codes.get(&id).map(String::as_str)))
+        .collect())
+}
+
+// Path of the first worksheet, found through the workbook relationships
+fn first_sheet_path<R: Read + Seek>(archive: &mut ZipArchive<R>) -> Result<String, Error> {
+    let fallback = "xl/worksheets/sheet1.xml".to_owned();
+    let first_id = {
+        let xml = read_entry(archive, "xl/workbook.xml")?;
+        let mut reader = Reader::from_str(&xml);
+        let mut buf = Vec::new();
+        let mut id = None;
+        loop {
+            match reader.read_event(&mut buf)? {
+                Event::Start(ref element) | Event::Empty(ref element)
+                    if element.name() == b"sheet" =>
+                {
+                    id = attribute(element, b"r:id")?;
+                    break;
+                }
+                Event::Eof => break,
+                _ => (),
+            }
+            buf.clear();
+        }
+        match id {
+            Some(id) => id,
+            None => return Ok(fallback),
+        }
+    };
+    let xml = match read_entry(archive, "xl/_rels/workbook.xml.rels") {
+        Ok(xml) => xml,
+        Err(_) => return Ok(fallback),
+    };
+    let mut reader = Reader::from_str(&xml);
+    let mut buf = Vec::new();
+    loop {
+        match reader.read_event(&mut buf)? {
+            Event::Start(ref element) | Event::Empty(ref element)
+                if element.name() == b"Relationship" =>
+            {
+                let target = attribute(element, b"Target")?;
+                match target {
+                    Some(target) if attribute(element, b"Id")?.as_deref() == Some(&first_id) => {
+                        return Ok(match target.strip_prefix('/') {
+                            Some(absolute) => absolute.to_owned(),
+                            None => format!("xl/{}", target),
+                        });
+                    }
+                    _ => (),
+                }
+            }
+            Event::Eof => return Ok(fallback),
+            _ => (),
+        }
+        buf.clear();
+    }
+}
+
+// Excel stores dates as days since 1899-12-30 with the time as a fraction of a day
+fn serial_to_text(serial: &str, kind: ColumnType) -> String {
+    let serial: f64 = match serial.trim().parse() {
+        Ok(serial) => serial,
+        Err(_) => return serial.to_owned(),
+    };
+    let mut days = serial.floor() as i64;
+    let mut seconds = ((serial - serial.floor()) * 86400.0).round() as i64;
+    if seconds == 86400 {
+        days += 1;
+        seconds = 0;
+    }
+    let date = match Date::from_days(days - EXCEL_EPOCH_DAYS) {
+        Some(date) => date,
+        None => return serial.to_string(),
+    };
+    match kind {
+        ColumnType::Timestamp => DateTime {
+            date,
+            hour: (seconds / 3600) as u8,
+            minute: (seconds / 60 % 60) as u8,
+            second: (seconds % 60) as u8,
+        }
+        .to_string(),
+        _ => date.to_string(),
+    }
+}
+
+fn date_to_serial(date: &Date) -> i64 {
+    date.days_since_epoch() + EXCEL_EPOCH_DAYS
+}
+
+const XLSX_CONTENT_TYPES: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types">
+<Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml"/>
+<Default Extension="xml" ContentType="application/xml"/>
+<Override PartName="/xl/workbook.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.sheet.main+xml"/>
+<Override PartName="/xl/worksheets/sheet1.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.worksheet+xml"/>
+<Override PartName="/xl/styles.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.styles+xml"/>
+</Types>"#;
+
+const XLSX_RELS: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">
+<Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument" Target="xl/workbook.xml"/>
+</Relationships>"#;
+
+const XLSX_WORKBOOK: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<workbook xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships">
+<sheets><sheet name="Sheet1" sheetId="1" r:id="rId1"/></sheets>
+</workbook>"#;
+
+const XLSX_WORKBOOK_RELS: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">
+<Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/worksheet" Target="worksheets/sheet1.xml"/>
+<Relationship Id="rId2" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/styles" Target="styles.xml"/>
+</Relationships>"#;
+
+// Cell styles: 0 default, 1 date, 2 timestamp, 3 bold header
+const XLSX_STYLES: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<styleSheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main">
+<numFmts count="2"><numFmt numFmtId="164" formatCode="yyyy-mm-dd"/><numFmt numFmtId="165" formatCode="yyyy-mm-dd hh:mm:ss"/></numFmts>
+<fonts count="2"><font><sz val="11"/><name val="Calibri"/></font><font><b/><sz val="11"/><name val="Calibri"/></font></fonts>
+<fills count="2"><fill><patternFill patternType="none"/></fill><fill><patternFill patternType="gray125"/></fill></fills>
+<borders count="1"><border><left/><right/><top/><bottom/><diagonal/></border></borders>
+<cellStyleXfs count="1"><xf numFmtId="0" fontId="0" fillId="0" borderId="0"/></cellStyleXfs>
+<cellXfs count="4">
+<xf numFmtId="0" fontId="0" fillId="0" borderId="0" xfId="0"/>
+<xf numFmtId="164" fontId="0" fillId="0" borderId="0" xfId="0" applyNumberFormat="1"/>
+<xf numFmtId="165" fontId="0" fillId="0" borderId="0" xfId="0" applyNumberFormat="1"/>
+<xf numFmtId="0" fontId="1" fillId="0" borderId="0" xfId="0" applyFont="1"/>
+</cellXfs>
+</styleSheet>"#;
+
+fn xlsx_text_cell(reference: &str, style: u8, text: &str) -> String {
+    format!(
+        r#"<c r="{}" s="{}" t="inlineStr"><is><t xml:space="preserve">{}</t></is></c>"#,
+        reference,
+        style,
+        escape(text)
+    )
+}
+
+fn xlsx_cell(reference: &str, value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::Integer(_) | Value::Float(_) | Value::Decimal(_) => {
+            format!(r#"<c r="{}"><v>{}</v></c>"#, reference, value)
+        }
+        Value::Boolean(value) => {
+            format!(r#"<c r="{}" t="b"><v>{}</v></c>"#, reference, *value as u8)
+        }
+        Value::Date(date) => format!(
+            r#"<c r="{}" s="1"><v>{}</v></c>"#,
+            reference,
+            date_to_serial(date)
+        ),
+        Value::Timestamp(timestamp) => {
+            let seconds = u32::from(timestamp.hour) * 3600
+                + u32::from(timestamp.minute) * 60
+                + u32::from(timestamp.second);
+            let serial = date_to_serial(&timestamp.date) as f64 + f64::from(seconds) / 86400.0;
+            format!(r#"<c r="{}" s="2"><v>{}</v></c>"#, reference, serial)
+        }
+        Value::Text(text) => xlsx_text_cell(reference, 0, text),
+    }
+}
+
+fn write_xlsx<W: Write + Seek>(
+    zip: &mut ZipWriter<W>,
+    columns: &[Column],
+    rows: &[Row],
+    widths: &[u32],
+) -> Result<(), Error> {
+    let mut sheet = String::from(
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<worksheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"><cols>"#,
+    );
+    for (col, width) in widths.iter().enumerate() {
+        sheet.push_str(&format!(
+            r#"<col min="{0}" max="{0}" width="{1:.4}" customWidth="1"/>"#,
+            col + 1,
+            f64::from(*width) / PIXELS_PER_CHARACTER
+        ));
+    }
+    sheet.push_str(r#"</cols><sheetData><row r="1">"#);
+    for (col, column) in columns.iter().enumerate() {
+        let reference = format!("{}1", column_label(col));
+        sheet.push_str(&xlsx_text_cell(&reference, 3, &column.name));
+    }
+    sheet.push_str("</row>");
+    for (index, row) in rows.iter().enumerate() {
+        let line = index + 2;
+        sheet.push_str(&format!(r#"<row r="{}">"#, line));
+        for (col, value) in row.values.iter().enumerate() {
+            let reference = format!("{}{}", column_label(col), line);
+            sheet.push_str(&xlsx_cell(&reference, value));
+        }
+        sheet.push_str("</row>");
+    }
+    sheet.push_str("</sheetData></worksheet>");
+
+    add_entry(zip, "[Content_Types].xml", XLSX_CONTENT_TYPES)?;
+    add_entry(zip, "_rels/.rels", XLSX_RELS)?;
+    add_entry(zip, "xl/workbook.xml", XLSX_WORKBOOK)?;
+    add_entry(zip, "xl/_rels/workbook.xml.rels", XLSX_WORKBOOK_RELS)?;
+    add_entry(zip, "xl/styles.xml", XLSX_STYLES)?;
+    add_entry(zip, "xl/worksheets/sheet1.xml", &sheet)
+}
+
+const ODS_MIMETYPE: &str = "application/vnd.oasis.opendocument.spreadsheet";
+
+const ODS_MANIFEST: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<manifest:manifest xmlns:manifest="urn:oasis:names:tc:opendocument:xmlns:manifest:1.0" manifest:version="1.2">
+<manifest:file-entry manifest:full-path="/" manifest:version="1.2" manifest:media-type="application/vnd.oasis.opendocument.spreadsheet"/>
+<manifest:file-entry manifest:full-path="content.xml" manifest:media-type="text/xml"/>
+</manifest:manifest>"#;
+
+// Data styles showing dates as YYYY-MM-DD and timestamps as YYYY-MM-DD HH:MM:SS
+const ODS_DATE_STYLES: &str = r#"<number:date-style style:name="N1"><number:year number:style="long"/><number:text>-</number:text><number:month number:style="long"/><number:text>-</number:text><number:day number:style="long"/></number:date-style>
+<number:date-style style:name="N2"><number:year number:style="long"/><number:text>-</number:text><number:month number:style="long"/><number:text>-</number:text><number:day number:style="long"/><number:text> </number:text><number:hours number:style="long"/><number:text>:</number:text><number:minutes number:style="long"/><number:text>:</number:text><number:seconds number:style="long"/></number:date-style>
+<style:style style:name="date" style:family="table-cell" style:data-style-name="N1"/>
+<style:style style:name="timestamp" style:family="table-cell" style:data-style-name="N2"/>
+<style:style style:name="header" style:family="table-cell"><style:text-properties fo:font-weight="bold"/></style:style>
+"#;
+
+fn ods_cell_xml(value: &Value) -> String {
+    let text = escape(&value.to_string());
+    match value {
+        Value::Null => "<table:table-cell/>".to_owned(),
+        Value::Integer(_) | Value::Float(_) | Value::Decimal(_) => format!(
+            r#"<table:table-cell office:value-type="float" office:value="{0}"><text:p>{0}</text:p></table:table-cell>"#,
+            text
+        ),
+        Value::Boolean(value) => format!(
+            r#"<table:table-cell office:value-type="boolean" office:boolean-value="{0}"><text:p>{0}</text:p></table:table-cell>"#,
+            value
+        ),
+        Value::Date(date) => format!(
+            r#"<table:table-cell table:style-name="date" office:value-type="date" office:date-value="{0}"><text:p>{0}</text:p></table:table-cell>"#,
+            date
+        ),
+        Value::Timestamp(timestamp) => format!(
+            r#"<table:table-cell table:style-name="timestamp" office:value-type="date" office:date-value="{}T{:02}:{:02}:{:02}"><text:p>{}</text:p></table:table-cell>"#,
+            timestamp.date, timestamp.hour, timestamp.minute, timestamp.second, timestamp
+        ),
+        Value::Text(_) => ods_text_cell("", &text),
+    }
+}
+
+// `text` is already escaped
+fn ods_text_cell(style: &str, text: &str) -> String {
+    let style = if style.is_empty() {
+        String::new()
+    } else {
+        format!(r#" table:style-name="{}""#, style)
+    };
+    let paragraphs: Vec<String> = text
+        .split('\n')
+        .map(|line| format!("<text:p>{}</text:p>", line))
+        .collect();
+    format!(
+        r#"<table:table-cell{} office:value-type="string">{}</table:table-cell>"#,
+        style,
+        paragraphs.concat()
+    )
+}
+
+fn write_ods<W: Write + Seek>(
+    zip: &mut ZipWriter<W>,
+    columns: &[Column],
+    rows: &[Row],
+    widths: &[u32],
+) -> Result<(), Error> {
+    let mut content = String::from(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<office:document-content xmlns:office="urn:oasis:names:tc:opendocument:xmlns:office:1.0" xmlns:style="urn:oasis:names:tc:opendocument:xmlns:style:1.0" xmlns:text="urn:oasis:names:tc:opendocument:xmlns:text:1.0" xmlns:table="urn:oasis:names:tc:opendocument:xmlns:table:1.0" xmlns:fo="urn:oasis:names:tc:opendocument:xmlns:xsl-fo-compatible:1.0" xmlns:number="urn:oasis:names:tc:opendocument:xmlns:datastyle:1.0" office:version="1.2">
+<office:automatic-styles>
+"#,
+    );
+    for (col, width) in widths.iter().enumerate() {
+        content.push_str(&format!(
+            r#"<style:style style:name="co{}" style:family="table-column"><style:table-column-properties style:column-width="{:.4}in"/></style:style>"#,
+            col + 1,
+            f64::from(*width) / PIXELS_PER_INCH
+        ));
+    }
+    content.push_str(ODS_DATE_STYLES);
+    content.push_str(
+        r#"</office:automatic-styles><office:body><office:spreadsheet><table:table table:name="Sheet1">"#,
+    );
+    for col in 0..widths.len() {
+        content.push_str(&format!(
+            r#"<table:table-column table:style-name="co{}"/>"#,
+            col + 1
+        ));
+    }
+    content.push_str("<table:table-row>");
+    for column in columns {
+        content.push_str(&ods_text_cell("header", &escape(&column.name)));
+    }
+    content.push_str("</table:table-row>");
+    for row in rows {
+        content.push_str("<table:table-row>");
+        for value in &row.values {
+            content.push_str(&ods_cell_xml(value));
+        }
+        content.push_str("</table:table-row>");
+    }
+    content.push_str("</table:table></office:spreadsheet></office:body></office:document-content>");
+
+    // The mimetype comes first and uncompressed, so the format can be told without unzipping
+    zip.start_file(
+        "mimetype",
+        FileOptions::default().compression_method(CompressionMethod::Stored),
+    )?;
+    zip.write_all(ODS_MIMETYPE.as_bytes())?;
+    add_entry(zip, "META-INF/manifest.xml", ODS_MANIFEST)?;
+    add_entry(zip, "content.xml", &content)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::database::{example_columns, example_rows};
+    use crate::transfer::ImportPlan;
+    use crate::value::Value;
+    use crate::workbook::{Format, Workbook};
+
+    #[test]
+    fn test_workbook_round_trip() {
+        let columns = example_columns();
+        let widths = vec![170, 100, 80, 245];
+        let expected: Vec<Vec<Value>> = example_rows().into_iter().map(|row| row.values).collect();
+
+        for format in &[Format::Xlsx, Format::Ods] {
+            let file = Workbook::write(
+                Cursor::new(Vec::new()),
+                *format,
+                &columns,
+                &example_rows(),
+                &widths,
+            )
+            .unwrap();
+            let workbook = Workbook::read(Cursor::new(file.into_inner()), *format).unwrap();
+
+            // Columns without a width get the default
+            let mut expected_widths: Vec<Option<u32>> = widths.iter().copied().map(Some).collect();
+            expected_widths.resize(columns.len(), Some(170));
+            assert_eq!(workbook.widths, expected_widths, "{:?}", format);
+
+            let plan = ImportPlan::new(&workbook.records, None, &columns, &[]);
+            assert!(plan.header);
+            assert!(plan.errors.is_empty(), "{:?}", plan.errors);
+            let values: Vec<Vec<Value>> = plan.rows.into_iter().map(|row| row.values).collect();
+            assert_eq!(values, expected, "{:?}", format);
+        }
+    }
+}